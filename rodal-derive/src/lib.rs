@@ -0,0 +1,250 @@
+// Copyright 2017 The Australian National University
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/*
+Documentation:
+(these are re-exported by rodal when it's "derive" feature is enabled)
+
+#[derive(Dump)]
+	structs and tuple structs:
+		like rodal_unordered_struct!, dumps every field in memory order (with padding between them)
+	enums:
+		like rodal_enum!, dumps the fields of the current variant in memory order,
+		and the bytes around them (which may contain the discriminant) as raw values
+	unions:
+		like rodal_value!, dumps the union as raw bytes (as we can't tell which field is active)
+	every type parameter must implement Dump
+	Dump::NO_POINTERS is set for unions, and for structs with no padding whose fields have no pointers
+	(or are marked #[rodal(value)])
+
+#[derive(Named)]
+	names the type 'Name' or 'Name<A, B, ...>' (the names of it's type parameters and the values of it's const parameters),
+	every type parameter must implement Named
+
+Field attributes (for structs and enums):
+	#[rodal(skip)]
+		dump the field as padding (i.e. zeros)
+	#[rodal(value)]
+		dump the field as a raw sequence of bytes (the field's type needn't implement Dump)
+	#[rodal(with = path)]
+		dump the field by calling path(&field, dumper), where path has the signature
+		fn<D: ? Sized + Dumper>(&FieldType, &mut D) (like Dump::dump, it must start with a call to debug_record)
+*/
+
+extern crate proc_macro;
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, GenericParam, Generics, Path};
+
+#[proc_macro_derive(Dump, attributes(rodal))]
+pub fn derive_dump(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match dump_impl(input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into()
+    }
+}
+
+#[proc_macro_derive(Named)]
+pub fn derive_named(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    named_impl(input).into()
+}
+
+// How to dump a field
+enum FieldKind {
+    Object,
+    Skip,
+    Value,
+    With(Path)
+}
+
+fn field_kind(field: &syn::Field) -> syn::Result<FieldKind> {
+    let mut kind = FieldKind::Object;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("rodal")) {
+        attr.parse_nested_meta(|meta| {
+            if !matches!(kind, FieldKind::Object) {
+                return Err(meta.error("only one rodal attribute may be given per field"));
+            }
+            kind = if meta.path.is_ident("skip") {
+                FieldKind::Skip
+            } else if meta.path.is_ident("value") {
+                FieldKind::Value
+            } else if meta.path.is_ident("with") {
+                FieldKind::With(meta.value()?.parse()?)
+            } else {
+                return Err(meta.error("expected `skip`, `value` or `with = path`"));
+            };
+            Ok(())
+        })?;
+    }
+    Ok(kind)
+}
+
+// Returns the statement that adds 'value' (an expression of type &FieldType) to 'list'
+// (a skipped field is still added as padding if the gaps between fields will be dumped as values)
+fn add_field(field: &syn::Field, value: TokenStream, gaps: bool) -> syn::Result<TokenStream> {
+    Ok(match field_kind(field)? {
+        FieldKind::Object => quote!(list.add(#value);),
+        FieldKind::Skip if gaps => quote!(list.add_padding(#value);),
+        FieldKind::Skip => quote!(),
+        FieldKind::Value => quote!(list.add_value(#value);),
+        FieldKind::With(path) => quote!(list.add_with(#value, #path);)
+    })
+}
+
+fn add_bounds(mut generics: Generics, bound: TokenStream) -> Generics {
+    for param in &mut generics.params {
+        if let GenericParam::Type(ref mut param) = *param {
+            param.bounds.push(parse_quote!(#bound));
+        }
+    }
+    generics
+}
+
+fn dump_impl(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let mut no_pointers = quote!();
+    let body = match input.data {
+        Data::Struct(ref data) => {
+            // A struct has no pointers if none of it's fields do, and it has no padding
+            // (as that would otherwise be dumped as zeros)
+            let mut fields_no_pointers = Vec::new();
+            let mut sizes = Vec::new();
+            for field in &data.fields {
+                let ty = &field.ty;
+                fields_no_pointers.push(match field_kind(field)? {
+                    FieldKind::Object => quote!(<#ty as ::rodal::Dump>::NO_POINTERS),
+                    FieldKind::Value => quote!(true),
+                    FieldKind::Skip | FieldKind::With(_) => quote!(false)
+                });
+                sizes.push(quote!(::std::mem::size_of::<#ty>()));
+            }
+            no_pointers = quote! {
+                const NO_POINTERS: bool = true #(&& #fields_no_pointers)* &&
+                    0 #(+ #sizes)* == ::std::mem::size_of::<Self>();
+            };
+
+            let mut adds = Vec::new();
+            for (i, field) in data.fields.iter().enumerate() {
+                let member = match field.ident {
+                    Some(ref ident) => quote!(#ident),
+                    None => {
+                        let index = syn::Index::from(i);
+                        quote!(#index)
+                    }
+                };
+                adds.push(add_field(field, quote!(&self.#member), false)?);
+            }
+            quote! {
+                let mut list = ::rodal::DumpList::<__D>::new();
+                #(#adds)*
+                list.dump(dumper);
+            }
+        }
+        Data::Enum(ref data) => {
+            let mut arms = Vec::new();
+            for variant in &data.variants {
+                let variant_name = &variant.ident;
+                let bindings: Vec<_> = (0..variant.fields.len()).map(|i| format_ident!("field{}", i)).collect();
+                let pattern = match variant.fields {
+                    Fields::Named(ref fields) => {
+                        let names = fields.named.iter().map(|field| &field.ident);
+                        quote!(#name::#variant_name { #(#names: ref #bindings),* })
+                    }
+                    Fields::Unnamed(_) => quote!(#name::#variant_name(#(ref #bindings),*)),
+                    Fields::Unit => quote!(#name::#variant_name)
+                };
+                if variant.fields.is_empty() {
+                    arms.push(quote!(#pattern => dumper.dump_value(self),));
+                    continue;
+                }
+                let mut adds = Vec::new();
+                for (field, binding) in variant.fields.iter().zip(&bindings) {
+                    adds.push(add_field(field, quote!(#binding), true)?);
+                }
+                arms.push(quote! {
+                    #pattern => {
+                        let mut list = ::rodal::DumpList::<__D>::new();
+                        #(#adds)*
+                        list.dump_with_gaps(dumper);
+                        dumper.dump_suffix_value(self);
+                    }
+                });
+            }
+            quote! {
+                match *self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(ref data) => {
+            let mut attrs = data.fields.named.iter().flat_map(|field| &field.attrs);
+            if let Some(attr) = attrs.find(|attr| attr.path().is_ident("rodal")) {
+                return Err(syn::Error::new(attr.span(), "rodal attributes can't be used on the fields of a union"));
+            }
+            no_pointers = quote!(const NO_POINTERS: bool = true;);
+            quote!(dumper.dump_value(self);)
+        }
+    };
+
+    let generics = add_bounds(input.generics.clone(), quote!(::rodal::Dump));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        unsafe impl #impl_generics ::rodal::Dump for #name #ty_generics #where_clause {
+            fn dump<__D: ?::std::marker::Sized + ::rodal::Dumper>(&self, dumper: &mut __D) {
+                dumper.debug_record::<Self>("dump");
+                #body
+            }
+            #no_pointers
+        }
+    })
+}
+
+fn named_impl(input: DeriveInput) -> TokenStream {
+    let name = &input.ident;
+    let params: Vec<_> = input
+        .generics
+        .params
+        .iter()
+        .filter_map(|param| match *param {
+            GenericParam::Type(ref param) => {
+                let ident = &param.ident;
+                Some(quote!(::rodal::type_name::<#ident>()))
+            }
+            GenericParam::Const(ref param) => {
+                let ident = &param.ident;
+                Some(quote!(#ident.to_string()))
+            }
+            GenericParam::Lifetime(_) => None
+        })
+        .collect();
+    let body = if params.is_empty() {
+        quote!(stringify!(#name).to_string())
+    } else {
+        quote!(format!("{}<{}>", stringify!(#name), [#(#params),*].join(", ")))
+    };
+
+    let generics = add_bounds(input.generics.clone(), quote!(::rodal::Named));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::rodal::Named for #name #ty_generics #where_clause {
+            fn name() -> ::std::string::String {
+                #body
+            }
+        }
+    }
+}
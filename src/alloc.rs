@@ -14,22 +14,68 @@
 
 extern crate libc;
 
+use std::alloc::{GlobalAlloc, Layout};
 use std::mem;
-use std::sync::atomic::{fence, Ordering};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
 use super::*;
 
 fn is_rodal_dump(ptr: *const libc::c_void) -> bool {
     let ptr = Address::from_ptr(ptr);
-    match unsafe { RODAL_BOUND } {
+    let in_bound = |bound: Option<(Address, Address)>| match bound {
         Some((start, end)) => start <= ptr && ptr < end,
         _ => false
+    };
+    // Check the main program's dump first, and then any dynamically loaded images
+    unsafe { in_bound(RODAL_BOUND) || (*ptr::addr_of!(RODAL_IMAGE_BOUNDS)).iter().any(|&bound| in_bound(bound)) }
+}
+
+// The dumper writes the size of every object immediately before it
+unsafe fn dump_object_size(ptr: *const libc::c_void) -> usize {
+    *(Address::from_ptr(ptr) - mem::size_of::<libc::size_t>()).to_ref::<usize>()
+}
+// And the alignment immediately before the size
+unsafe fn dump_object_alignment(ptr: *const libc::c_void) -> usize {
+    *(Address::from_ptr(ptr) - 2 * mem::size_of::<libc::size_t>()).to_ref::<usize>()
+}
+
+// The alignment malloc guarantees (glibc guarantees 2*sizeof(size_t), other libcs are at least as strict)
+const MALLOC_ALIGNMENT: usize = 2 * mem::size_of::<libc::size_t>();
+
+// Mallocs an area with the given alignment (returns null if it failed)
+unsafe fn aligned_malloc(size: libc::size_t, alignment: usize) -> *mut libc::c_void {
+    if alignment <= MALLOC_ALIGNMENT {
+        libc::malloc(size)
+    } else {
+        let mut ptr = ptr::null_mut();
+        if libc::posix_memalign(&mut ptr, alignment, size) == 0 {
+            ptr
+        } else {
+            ptr::null_mut()
+        }
+    }
+}
+
+// Copies a dumped object to a new (really malloced) area with the given alignment
+unsafe fn copy_dump_object(ptr: *mut libc::c_void, new_size: libc::size_t, alignment: usize) -> *mut libc::c_void {
+    let new_ptr = aligned_malloc(new_size, alignment);
+    if !new_ptr.is_null() {
+        let copied = std::cmp::min(dump_object_size(ptr), new_size);
+        libc::memcpy(new_ptr, ptr, copied);
+        record_event(RodalAllocEventKind::CopiedRealloc, ptr as usize, copied);
     }
+    new_ptr
 }
 
 const FREE_NAME: &'static [u8] = b"free\0";
 const REALLOC_NAME: &'static [u8] = b"realloc\0";
+#[cfg(not(target_os = "macos"))]
+const MALLOC_USABLE_SIZE_NAME: &[u8] = b"malloc_usable_size\0";
+#[cfg(target_os = "macos")]
+const MALLOC_USABLE_SIZE_NAME: &[u8] = b"malloc_size\0";
 static mut REAL_FREE: Option<extern "C" fn(*mut libc::c_void)> = None;
 static mut REAL_REALLOC: Option<extern "C" fn(*mut libc::c_void, libc::size_t) -> (*mut libc::c_void)> = None;
+static mut REAL_MALLOC_USABLE_SIZE: Option<extern "C" fn(*mut libc::c_void) -> libc::size_t> = None;
 
 // This should be the first thing called in main
 #[no_mangle]
@@ -44,6 +90,13 @@ pub unsafe extern "C" fn rodal_init_deallocate() {
         REALLOC_NAME.as_ptr() as *const libc::c_char,
     )));
     assert!(REAL_REALLOC.is_some());
+    // Not every allocator provides this, without it the sizes of (non dump) allocations are unknown
+    let malloc_usable_size = libc::dlsym(libc::RTLD_NEXT, MALLOC_USABLE_SIZE_NAME.as_ptr() as *const libc::c_char);
+    REAL_MALLOC_USABLE_SIZE = if malloc_usable_size.is_null() {
+        None
+    } else {
+        Some(mem::transmute::<*mut libc::c_void, extern "C" fn(*mut libc::c_void) -> libc::size_t>(malloc_usable_size))
+    };
 
     // Make sure other threads (when they start) see the writes to the global variables
     fence(Ordering::SeqCst);
@@ -62,24 +115,347 @@ pub unsafe extern "C" fn rodal_init_deallocate_explicit(
     fence(Ordering::Release);
 }
 
+/// Only needed if rodal_malloc_usable_size or rodal_aligned_realloc is used
+/// (rodal_init_deallocate finds this automatically)
+///
+/// # Safety
+/// This must be called before any other thread uses the allocator shim
+#[no_mangle]
+pub unsafe extern "C" fn rodal_init_deallocate_explicit_usable_size(
+    malloc_usable_size: extern "C" fn(*mut libc::c_void) -> libc::size_t,
+) {
+    REAL_MALLOC_USABLE_SIZE = Some(malloc_usable_size);
+
+    fence(Ordering::Release);
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn rodal_free(ptr: *mut libc::c_void) {
     if !is_rodal_dump(ptr) {
         (REAL_FREE.unwrap())(ptr);
+    } else {
+        record_event(RodalAllocEventKind::IgnoredFree, ptr as usize, dump_object_size(ptr));
     }
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn rodal_realloc(ptr: *mut libc::c_void, new_size: libc::size_t) -> *mut libc::c_void {
     if is_rodal_dump(ptr) {
-        let old_size = *(Address::from_ptr(ptr) - mem::size_of::<libc::size_t>()).to_ref::<usize>();
+        let old_size = dump_object_size(ptr);
         if old_size >= new_size {
+            record_event(RodalAllocEventKind::InPlaceRealloc, ptr as usize, new_size);
             ptr // Allocated area is large enough
         } else {
-            // Have to copy to a new (really malloced) area
-            libc::memcpy(libc::malloc(new_size), ptr, old_size)
+            // Have to copy to a new (really malloced) area, that is aligned like the original object
+            copy_dump_object(ptr, new_size, dump_object_alignment(ptr))
         }
     } else {
         (REAL_REALLOC.unwrap())(ptr, new_size)
     }
 }
+
+/// Returns 0 for memory outside of the dump if the allocator has no malloc_usable_size
+///
+/// # Safety
+/// Like malloc_usable_size, ptr must have been allocated by malloc (or be in a dump)
+#[no_mangle]
+pub unsafe extern "C" fn rodal_malloc_usable_size(ptr: *mut libc::c_void) -> libc::size_t {
+    if is_rodal_dump(ptr) {
+        dump_object_size(ptr)
+    } else {
+        match REAL_MALLOC_USABLE_SIZE {
+            Some(malloc_usable_size) => malloc_usable_size(ptr),
+            None => 0
+        }
+    }
+}
+
+/// # Safety
+/// Like reallocarray, ptr must be null or have been allocated by malloc (or be in a dump)
+#[no_mangle]
+pub unsafe extern "C" fn rodal_reallocarray(
+    ptr: *mut libc::c_void,
+    count: libc::size_t,
+    size: libc::size_t,
+) -> *mut libc::c_void {
+    match count.checked_mul(size) {
+        Some(new_size) => rodal_realloc(ptr, new_size),
+        None => {
+            set_errno(libc::ENOMEM);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Like realloc, but the result will be aligned to 'alignment'
+/// (like aligned_alloc, if alignment isn't a power of two this fails with EINVAL)
+///
+/// # Safety
+/// Like realloc, ptr must be null or have been allocated by malloc (or be in a dump)
+#[no_mangle]
+pub unsafe extern "C" fn rodal_aligned_realloc(
+    ptr: *mut libc::c_void,
+    alignment: libc::size_t,
+    new_size: libc::size_t,
+) -> *mut libc::c_void {
+    if !alignment.is_power_of_two() {
+        set_errno(libc::EINVAL);
+        ptr::null_mut()
+    } else if ptr.is_null() {
+        aligned_malloc(new_size, alignment)
+    } else if is_rodal_dump(ptr) {
+        if dump_object_size(ptr) >= new_size && Address::from_ptr(ptr).value() & (alignment - 1) == 0 {
+            record_event(RodalAllocEventKind::InPlaceRealloc, ptr as usize, new_size);
+            ptr // Allocated area is large enough (and aligned enough)
+        } else {
+            copy_dump_object(ptr, new_size, std::cmp::max(alignment, dump_object_alignment(ptr)))
+        }
+    } else if alignment <= MALLOC_ALIGNMENT {
+        (REAL_REALLOC.unwrap())(ptr, new_size) // realloc already guarantees this alignment
+    } else {
+        // realloc could lose the alignment, so move it ourselves
+        let new_ptr = aligned_malloc(new_size, alignment);
+        if new_ptr.is_null() {
+            return new_ptr;
+        }
+        match REAL_MALLOC_USABLE_SIZE {
+            Some(malloc_usable_size) => {
+                libc::memcpy(new_ptr, ptr, std::cmp::min(malloc_usable_size(ptr), new_size));
+                (REAL_FREE.unwrap())(ptr);
+            }
+            None => {
+                // We don't know how big ptr is, but realloc does (and if it fails, ptr is left alone)
+                let resized = (REAL_REALLOC.unwrap())(ptr, new_size);
+                if resized.is_null() {
+                    (REAL_FREE.unwrap())(new_ptr);
+                    return resized;
+                }
+                libc::memcpy(new_ptr, resized, new_size);
+                (REAL_FREE.unwrap())(resized);
+            }
+        }
+        new_ptr
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "redox"))]
+unsafe fn set_errno(value: libc::c_int) {
+    *libc::__errno_location() = value;
+}
+#[cfg(target_os = "macos")]
+unsafe fn set_errno(value: libc::c_int) {
+    *libc::__error() = value;
+}
+
+/// What the allocator shim did with a dumped object
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RodalAllocEventKind {
+    /// free was called on it (and ignored)
+    IgnoredFree = 1,
+    /// realloc was called on it, and it was large enough
+    InPlaceRealloc = 2,
+    /// realloc was called on it, and it had to be copied out of the dump
+    CopiedRealloc = 3,
+}
+
+/// Counts of each kind of event, along with the bytes involved (the size of freed objects,
+/// the size requested by in place reallocs, and the number of bytes copied by copying reallocs)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RodalAllocStats {
+    pub ignored_frees: usize,
+    pub ignored_free_bytes: usize,
+    pub in_place_reallocs: usize,
+    pub in_place_realloc_bytes: usize,
+    pub copied_reallocs: usize,
+    pub copied_realloc_bytes: usize,
+}
+
+/// One entry in the allocator shim's event log
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RodalAllocEvent {
+    pub kind: RodalAllocEventKind,
+    /// The address of the dumped object
+    pub address: usize,
+    /// The bytes involved (as for RodalAllocStats)
+    pub size: usize,
+}
+
+// [count, bytes] for each RodalAllocEventKind
+static ALLOC_COUNTERS: [[AtomicUsize; 2]; 3] = [
+    [AtomicUsize::new(0), AtomicUsize::new(0)],
+    [AtomicUsize::new(0), AtomicUsize::new(0)],
+    [AtomicUsize::new(0), AtomicUsize::new(0)],
+];
+
+// The event log is a ring buffer of the most recent events.
+// It can't allocate (as it is called from within free and realloc)
+pub const RODAL_ALLOC_LOG_SIZE: usize = 1024;
+static ALLOC_LOG_ENABLED: AtomicBool = AtomicBool::new(false);
+// The total number of events that have been logged
+static ALLOC_LOG_NEXT: AtomicUsize = AtomicUsize::new(0);
+
+struct AllocLogSlot {
+    // The number of the event stored here plus one (zero means it hasn't been completely written yet)
+    sequence: AtomicUsize,
+    kind: AtomicUsize,
+    address: AtomicUsize,
+    size: AtomicUsize,
+}
+static ALLOC_LOG: [AllocLogSlot; RODAL_ALLOC_LOG_SIZE] = [const {
+    AllocLogSlot {
+        sequence: AtomicUsize::new(0),
+        kind: AtomicUsize::new(0),
+        address: AtomicUsize::new(0),
+        size: AtomicUsize::new(0),
+    }
+}; RODAL_ALLOC_LOG_SIZE];
+
+fn record_event(kind: RodalAllocEventKind, address: usize, size: usize) {
+    let counters = &ALLOC_COUNTERS[kind as usize - 1];
+    counters[0].fetch_add(1, Ordering::Relaxed);
+    counters[1].fetch_add(size, Ordering::Relaxed);
+
+    if ALLOC_LOG_ENABLED.load(Ordering::Relaxed) {
+        let number = ALLOC_LOG_NEXT.fetch_add(1, Ordering::Relaxed);
+        let slot = &ALLOC_LOG[number % RODAL_ALLOC_LOG_SIZE];
+        // A seqlock write: readers that see the new data also see the zero,
+        // and readers that see the new sequence number also see the new data
+        slot.sequence.store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        slot.kind.store(kind as usize, Ordering::Relaxed);
+        slot.address.store(address, Ordering::Relaxed);
+        slot.size.store(size, Ordering::Relaxed);
+        slot.sequence.store(number + 1, Ordering::Release);
+    }
+}
+
+/// Returns the counters for every event that has happened so far
+#[no_mangle]
+pub extern "C" fn rodal_alloc_stats() -> RodalAllocStats {
+    let get = |kind: RodalAllocEventKind, i: usize| ALLOC_COUNTERS[kind as usize - 1][i].load(Ordering::Relaxed);
+    RodalAllocStats {
+        ignored_frees: get(RodalAllocEventKind::IgnoredFree, 0),
+        ignored_free_bytes: get(RodalAllocEventKind::IgnoredFree, 1),
+        in_place_reallocs: get(RodalAllocEventKind::InPlaceRealloc, 0),
+        in_place_realloc_bytes: get(RodalAllocEventKind::InPlaceRealloc, 1),
+        copied_reallocs: get(RodalAllocEventKind::CopiedRealloc, 0),
+        copied_realloc_bytes: get(RodalAllocEventKind::CopiedRealloc, 1),
+    }
+}
+
+/// Turns the event log on or off (it is off by default)
+#[no_mangle]
+pub extern "C" fn rodal_alloc_set_logging(enabled: bool) {
+    ALLOC_LOG_ENABLED.store(enabled, Ordering::SeqCst);
+}
+
+/// Copies up to 'max' of the most recent logged events into 'out' (oldest first), returning how many were copied
+/// Events that are being written concurrently are skipped
+///
+/// # Safety
+/// out must be valid for writing 'max' events
+#[no_mangle]
+pub unsafe extern "C" fn rodal_alloc_events(out: *mut RodalAllocEvent, max: usize) -> usize {
+    let end = ALLOC_LOG_NEXT.load(Ordering::Acquire);
+    let count = std::cmp::min(std::cmp::min(end, RODAL_ALLOC_LOG_SIZE), max);
+    let mut copied = 0;
+    for number in end - count..end {
+        let slot = &ALLOC_LOG[number % RODAL_ALLOC_LOG_SIZE];
+        if slot.sequence.load(Ordering::Acquire) != number + 1 {
+            continue;
+        }
+        let kind = match slot.kind.load(Ordering::Relaxed) {
+            1 => RodalAllocEventKind::IgnoredFree,
+            2 => RodalAllocEventKind::InPlaceRealloc,
+            _ => RodalAllocEventKind::CopiedRealloc
+        };
+        let event = RodalAllocEvent {
+            kind,
+            address: slot.address.load(Ordering::Relaxed),
+            size: slot.size.load(Ordering::Relaxed),
+        };
+        // Make sure the slot wasn't overwritten whilst we read it
+        // (the fence orders the reads of the data before the second read of the sequence number)
+        fence(Ordering::Acquire);
+        if slot.sequence.load(Ordering::Relaxed) == number + 1 {
+            ptr::write(out.add(copied), event);
+            copied += 1;
+        }
+    }
+    copied
+}
+
+/// The most recent logged events (oldest first)
+pub fn rodal_alloc_event_log() -> Vec<RodalAllocEvent> {
+    let mut events = Vec::with_capacity(RODAL_ALLOC_LOG_SIZE);
+    unsafe {
+        let count = rodal_alloc_events(events.as_mut_ptr(), RODAL_ALLOC_LOG_SIZE);
+        events.set_len(count);
+    }
+    events
+}
+
+/// A GlobalAlloc that forwards to A, except for memory in a rodal dump
+/// (which was never allocated by A, so A must never free or reallocate it).
+/// Pure rust programs can use this as their #[global_allocator] instead of
+/// interposing rodal_free and rodal_realloc, e.g.:
+///     #[global_allocator]
+///     static GLOBAL: RodalAlloc<std::alloc::System> = RodalAlloc::new(std::alloc::System);
+pub struct RodalAlloc<A: GlobalAlloc> {
+    inner: A,
+}
+
+impl<A: GlobalAlloc> RodalAlloc<A> {
+    pub const fn new(inner: A) -> RodalAlloc<A> {
+        RodalAlloc { inner }
+    }
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for RodalAlloc<A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.inner.alloc(layout)
+    }
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        // Use the inner allocators version (it may know that fresh memory is already zeroed)
+        self.inner.alloc_zeroed(layout)
+    }
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !is_rodal_dump(ptr as *const libc::c_void) {
+            self.inner.dealloc(ptr, layout)
+        } else {
+            record_event(
+                RodalAllocEventKind::IgnoredFree,
+                ptr as usize,
+                dump_object_size(ptr as *const libc::c_void),
+            );
+        }
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if is_rodal_dump(ptr as *const libc::c_void) {
+            let old_size = dump_object_size(ptr as *const libc::c_void);
+            if old_size >= new_size {
+                record_event(RodalAllocEventKind::InPlaceRealloc, ptr as usize, new_size);
+                ptr // Allocated area is large enough
+            } else {
+                // Have to copy to a new area (that really was allocated by inner)
+                let new_ptr = self.inner.alloc(Layout::from_size_align_unchecked(new_size, layout.align()));
+                if !new_ptr.is_null() {
+                    ptr::copy_nonoverlapping(ptr, new_ptr, old_size);
+                    record_event(RodalAllocEventKind::CopiedRealloc, ptr as usize, old_size);
+                }
+                new_ptr
+            }
+        } else {
+            self.inner.realloc(ptr, layout, new_size)
+        }
+    }
+}
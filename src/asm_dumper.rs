@@ -13,9 +13,10 @@
 // limitations under the License.
 
 use num::integer::lcm;
-use std::collections::{BTreeSet, HashMap};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::error::Error;
 use std::fmt;
-use std::io::Write;
+use std::io::{BufWriter, Write};
 use std::mem;
 use super::*;
 
@@ -59,19 +60,6 @@ struct ObjectInfo<W: Write> {
     dump: DumpFunction<AsmDumper<W>>,
 }
 
-impl<W: Write> Clone for ObjectInfo<W> {
-    fn clone(&self) -> ObjectInfo<W> {
-        ObjectInfo::<W> {
-            start: self.start,
-            size: self.size,
-            alignment: self.alignment,
-            label: self.label.clone(),
-            value: self.value,
-            dump: self.dump,
-        }
-    }
-}
-
 impl<W: Write> ObjectInfo<W> {
     fn new(
         value: Address,
@@ -106,8 +94,10 @@ enum AsmDirective {
 }
 
 pub struct AsmDumper<W: Write> {
-    file: W,
+    file: BufWriter<W>,
     current_directive: AsmDirective,
+    /// Where byte directives are formatted before being written to file
+    byte_buffer: Vec<u8>,
 
     current_pointer: Address,
     // This is the pointer into the output we are dumping
@@ -119,54 +109,177 @@ pub struct AsmDumper<W: Write> {
     #[cfg(debug_assertions)]
     debug_indent: Vec<usize>, // How much to indent debugging info by
 
-    /// Objects we've already dumped
-    dumped_objects: HashMap<Address, ObjectInfo<W>>,
-
-    /// A table of all objects we haven't started dumping yet
-    pending_objects: HashMap<Address, ObjectInfo<W>>,
+    /// Every complete object we've dumped, or will dump (keyed by their start)
+    objects: BTreeMap<Address, ObjectInfo<W>>,
 
-    /// Objects we're currently dumping
-    dumping_objects: HashMap<Address, ObjectInfo<W>>,
+    /// The starts of the objects in 'objects' we haven't dumped yet (in the order they were referenced)
+    pending_objects: VecDeque<Address>,
 
     /// References that haven't been resolved to be relative to a complete object yet
     pending_references: BTreeSet<Address>,
+    /// The ranges given to forbid_references (keyed by their start), and the name of the type that owns them
+    forbidden: BTreeMap<Address, (Address, String)>,
     tags: HashMap<usize, Vec<*const ()>>,
+
+    /// The roots we've dumped, these are written to the root directory by finish
+    roots: Vec<RootRecord>,
+
+    /// Every object we've written (in order), if we've been asked to write an object table
+    object_table: Option<Vec<ObjectRecord>>,
+    /// The stubs that need to be rebuilt on loading (and the name of their type)
+    fixups: Vec<(AsmLabel, String)>,
+    /// Whether the next call to debug_record should record the type of the last object in object_table
+    naming_object: bool,
+    /// The number of strong and weak handles we've dumped to each counted object, if we're recounting references
+    reference_counts: Option<BTreeMap<Address, (usize, usize)>>,
+    /// Values given to keep_alive_raw
+    kept_alive: Vec<KeptAlive>,
+    /// The messages given to record_error (in order)
+    errors: Vec<String>,
 }
 
+// A value given to keep_alive_raw, and the function that destroys it
+type KeptAlive = (*mut (), unsafe fn(*mut ()));
+
+impl<W: Write> Drop for AsmDumper<W> {
+    fn drop(&mut self) {
+        for (value, drop) in self.kept_alive.drain(..) {
+            unsafe { drop(value) };
+        }
+    }
+}
+
+/// The errors recorded whilst dumping (see Dumper::record_error), the dump was still written,
+/// but the values they describe weren't dumped faithfully
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpError(pub Vec<String>);
+
+impl fmt::Display for DumpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the dump is incomplete: {}", self.0.join("; "))
+    }
+}
+
+impl Error for DumpError {}
+
+struct RootRecord {
+    name: String,
+    label: AsmLabel,
+    type_name: String,
+    size: usize,
+}
+
+struct ObjectRecord {
+    label: AsmLabel,
+    size: usize,
+    // The name of the type whose dump function dumped the object (and the name of the function if it isn't 'dump')
+    type_name: String,
+}
+
+// The start and end of the dump are aligned to this, so that the dump can be write protected
+const PAGE_ALIGNMENT: usize = 4096;
+/// The first word of every dump (at RODAL_START), "rodal" as little endian bytes
+pub const RODAL_MAGIC: usize = 0x6c61646f72;
+/// The version of the dump format, written after RODAL_MAGIC
+/// (this must be incremented whenever the layout of a dump, or of any of its tables, changes)
+pub const RODAL_FORMAT_VERSION: usize = 1;
+// The capacity of the BufWriter that AsmDumper::new puts in front of it's file
+const DEFAULT_BUFFER_CAPACITY: usize = 1 << 16;
+// The number of bytes write_bytes formats at a time
+const BYTE_CHUNK_SIZE: usize = 4096;
+static HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
 impl<W: Write> AsmDumper<W> {
+    // Creates a dumper that writes to file through a BufWriter with the given capacity
+    // (use a capacity of 0 if file is already buffered)
     #[cfg(debug_assertions)]
-    pub fn new(mut file: W) -> AsmDumper<W> {
+    pub fn with_capacity(file: W, capacity: usize) -> AsmDumper<W> {
+        check_std_layouts();
+        let mut file = BufWriter::with_capacity(capacity, file);
         writeln!(file, "#START RODAL DUMP").unwrap();
         writeln!(file, "\t.data").unwrap();
-        AsmDumper::<W> {
+        let mut dumper = AsmDumper::<W> {
             file: file,
             current_directive: AsmDirective::Other,
+            byte_buffer: Vec::new(),
             current_pointer: Address::null(),
             position_offset: 0,
             debug_stack: Vec::new(),
             debug_indent: Vec::new(),
-            dumped_objects: HashMap::new(),
-            pending_objects: HashMap::new(),
-            dumping_objects: HashMap::new(),
+            objects: BTreeMap::new(),
+            pending_objects: VecDeque::new(),
             pending_references: BTreeSet::new(),
+            forbidden: BTreeMap::new(),
             tags: HashMap::new(),
-        }
+            roots: Vec::new(),
+            fixups: Vec::new(),
+            object_table: None,
+            naming_object: false,
+            reference_counts: None,
+            kept_alive: Vec::new(),
+            errors: Vec::new(),
+        };
+        dumper.write_start();
+        dumper
     }
     #[cfg(not(debug_assertions))]
-    pub fn new(mut file: W) -> AsmDumper<W> {
+    pub fn with_capacity(file: W, capacity: usize) -> AsmDumper<W> {
+        check_std_layouts();
+        let mut file = BufWriter::with_capacity(capacity, file);
         writeln!(file, "#START RODAL DUMP").unwrap();
         writeln!(file, "\t.data").unwrap();
-        AsmDumper::<W> {
+        let mut dumper = AsmDumper::<W> {
             file: file,
             current_directive: AsmDirective::Other,
+            byte_buffer: Vec::new(),
             current_pointer: Address::null(),
             position_offset: 0,
-            dumped_objects: HashMap::new(),
-            pending_objects: HashMap::new(),
-            dumping_objects: HashMap::new(),
+            objects: BTreeMap::new(),
+            pending_objects: VecDeque::new(),
             pending_references: BTreeSet::new(),
+            forbidden: BTreeMap::new(),
             tags: HashMap::new(),
-        }
+            roots: Vec::new(),
+            fixups: Vec::new(),
+            object_table: None,
+            naming_object: false,
+            reference_counts: None,
+            kept_alive: Vec::new(),
+            errors: Vec::new(),
+        };
+        dumper.write_start();
+        dumper
+    }
+    // Creates a dumper that writes to file (through a BufWriter)
+    pub fn new(file: W) -> AsmDumper<W> {
+        AsmDumper::with_capacity(file, DEFAULT_BUFFER_CAPACITY)
+    }
+    // Write a label indicating the start of the rodal dump
+    fn write_start(&mut self) {
+        self.start_directive(AsmDirective::Other);
+        writeln!(self.file, "\t.balign {}", PAGE_ALIGNMENT).unwrap();
+        let start_label = AsmLabel::new("RODAL_START".to_string());
+        self.write_global(&start_label);
+        self.write_label_declaration(&start_label);
+        // So the loader can check that it understands the dump
+        self.write_value(RODAL_MAGIC);
+        self.write_value(RODAL_FORMAT_VERSION);
+    }
+    /// Whether to write a table describing every dumped object (RODAL_OBJECTS),
+    /// which is used to report which object was written to when the dump is write protected
+    /// (this must be called before anything is dumped)
+    pub fn set_object_table(&mut self, enabled: bool) -> &mut Self {
+        self.object_table = if enabled { Some(Vec::new()) } else { None };
+        self
+    }
+    /// Whether to replace the strong and weak counts of reference counted objects (e.g. an ArcInner or RcBox)
+    /// with the number of handles to them that are actually dumped (instead of dumping their current values)
+    /// (this must be called before anything is dumped)
+    /// An object that's only reached through Weak handles is still dumped (with it's payload),
+    /// but with a strong count of 0, so it can't be upgraded, and it's payload's destructor never runs
+    pub fn set_recount_references(&mut self, enabled: bool) -> &mut Self {
+        self.reference_counts = if enabled { Some(BTreeMap::new()) } else { None };
+        self
     }
     pub fn dump_sized<T: ? Sized + Dump>(&mut self, name: &str, value: &T, size: usize, alignment: usize) -> &mut Self {
         assert!(alignment != 0);
@@ -185,67 +298,228 @@ impl<W: Write> AsmDumper<W> {
         self.write_type_object(&label);
         self.write_size_align(size, alignment);
         self.write_label_declaration(&label);
+        self.record_object(&label, size);
         let dump_function = Self::get_dump_function::<T>();
-        self.dumped_objects.insert(
+        self.objects.insert(
             start,
             ObjectInfo::<W>::new(start, dump_function, start, size, alignment, label.clone()),
         );
         self.dump_object_function_here(value, dump_function);
         self.advance_position(start + size); // Add any neccesary padding
         self.write_size(&label);
+        self.roots.push(RootRecord {
+            name: name.to_string(),
+            label,
+            type_name: T::name(),
+            size,
+        });
         self
         // We finished dumping this root object
     }
 
+    /// Dumps the tags (as RODAL_TAGS), and then finishes the dump (so finish shouldn't be called afterwards)
     pub fn dump_tags(&mut self) {
-        self.finish(); // Dump eveything that might need to be tagged
+        self.dump_pending_objects(); // Dump eveything that might need to be tagged
 
-        // This is totally undefined bheaviour
-        // as this creates a immutable borrow to self (the reference to self.tags)
-        // and we then create a muttable borrow (to self in the call to self.dump)
-        //let tags = Address::new(&self.tags);
-        let tags: HashMap<usize, Vec<*const ()>> = self.tags.clone(); // This is soo unnecesary...
+        // Take the tags out of self whilst we dump them (as self.dump needs a mutable borrow to self),
+        // moving the map dosn't move it's contents, which may not have been dumped yet
+        let tags = mem::take(&mut self.tags);
         self.dump("RODAL_TAGS", &tags);
+        self.tags = tags;
+        self.finish();
+    }
+    // Dumps each pending object in the order it was referenced (which may add more objects to the end of the queue)
+    fn dump_pending_objects(&mut self) {
+        while let Some(start) = self.pending_objects.pop_front() {
+            let (label, size, alignment, value, dump) = {
+                let object = &self.objects[&start];
+                (object.label.clone(), object.size, object.alignment, object.value, object.dump)
+            };
+            self.current_pointer = start;
+
+            debug_only!({
+                trace!("");
+                trace!("dumping {} [{}, {:+}):", label.base.clone(), start, size)
+            });
+            self.write_type_object(&label);
+            self.write_size_align(size, alignment);
+            self.write_label_declaration(&label);
+            self.record_object(&label, size);
+            self.dump_object_function_here(value.to_ref::<()>(), dump);
+            self.advance_position(start + size);
+            self.write_size(&label);
+        }
     }
+    /// Finishes the dump, and then panics if any errors were recorded whilst dumping
     pub fn finish(&mut self) {
-        //trace!("{:?}: finish()", self.current_pointer);
-
-        // Still more objects to dump
-        while !self.pending_objects.is_empty() {
-            //self.dumping_objects.append(&mut self.pending_objects);
-            for (key, value) in self.pending_objects.drain() {
-                self.dumping_objects.insert(key, value);
-            }
-            for (start, value) in self.dumping_objects.clone() {
-                // Remove an element from the map (it dosn't mater which one)
-                // And add a copy to dumped_objects
-                self.current_pointer = start;
-
-                debug_only!({
-                    trace!("");
-                    trace!("dumping {} [{}, {:+}):", value.label.base.clone(), start, value.size)
-                });
-                self.write_type_object(&value.label);
-                self.write_size_align(value.size, value.alignment);
-                self.write_label_declaration(&value.label);
-                self.dump_object_function_here(value.value.to_ref::<()>(), value.dump);
-                self.advance_position(start + value.size);
-                self.write_size(&value.label);
-            }
-            for (key, value) in self.dumping_objects.drain() {
-                self.dumped_objects.insert(key, value);
-            }
-            //self.dumped_objects.append(&mut self.dumping_objects);
+        if let Err(err) = self.try_finish() {
+            panic!("{}", err);
         }
-
+    }
+    /// The errors recorded so far (see Dumper::record_error)
+    pub fn errors(&self) -> &[String] {
+        &self.errors
+    }
+    /// Finishes the dump, returning the errors recorded whilst dumping (if there were any)
+    /// (the dump is always completely written, even if this fails)
+    pub fn try_finish(&mut self) -> Result<(), DumpError> {
+        //trace!("{:?}: finish()", self.current_pointer);
+        self.dump_pending_objects();
         assert!(self.pending_references.is_empty()); // We should've dumped all referenced objects by now
+        self.write_reference_counts();
+
+        self.write_root_directory();
+        self.write_object_table();
+        self.write_fixup_table();
 
         // Write a label indicating the end of the rodal dump
+        self.start_directive(AsmDirective::Other);
+        writeln!(self.file, "\t.balign {}", PAGE_ALIGNMENT).unwrap();
         let end_label = AsmLabel::new("RODAL_END".to_string());
         self.write_global(&end_label);
         self.write_label_declaration(&end_label);
 
         writeln!(self.file, "#END RODAL DUMP").unwrap();
+        self.file.flush().unwrap();
+
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(DumpError(mem::take(&mut self.errors)))
+        }
+    }
+
+    // Defines the values of the reference counts we dumped (if we're recounting references)
+    fn write_reference_counts(&mut self) {
+        let counts = match self.reference_counts.take() {
+            Some(counts) => counts,
+            None => return
+        };
+        self.start_directive(AsmDirective::Other);
+        for (object, (strong, weak)) in counts {
+            // The strong handles collectively hold a weak reference
+            let weak = if strong > 0 { weak + 1 } else { weak };
+            writeln!(self.file, "\t.set {}, {}", reference_count_label(object, false).base, strong).unwrap();
+            writeln!(self.file, "\t.set {}, {}", reference_count_label(object, true).base, weak).unwrap();
+        }
+    }
+
+    // Writes the RODAL_ROOTS table, so that a loader can find every root without knowing its name
+    // (the layout of the table is given by RootTable and RootEntry in asm_loader.rs),
+    // the entries are sorted by name, so the loader can binary search it
+    fn write_root_directory(&mut self) {
+        let label = AsmLabel::new("RODAL_ROOTS".to_string());
+        let size = mem::size_of::<usize>() + self.roots.len() * mem::size_of::<RootEntry>();
+        self.write_global(&label);
+        self.write_type_object(&label);
+        self.write_size_align(size, mem::align_of::<RootEntry>());
+        self.write_label_declaration(&label);
+
+        let mut roots = mem::take(&mut self.roots);
+        roots.sort_by(|a, b| a.name.cmp(&b.name));
+        self.write_value(roots.len());
+        for (i, root) in roots.iter().enumerate() {
+            self.write_label_reference(AsmLabel::new(format!(".Lroot_name_{}", i)));
+            self.write_value(root.name.len());
+            self.write_label_reference(root.label.clone());
+            self.write_label_reference(AsmLabel::new(format!(".Lroot_type_{}", i)));
+            self.write_value(root.type_name.len());
+            self.write_value(root.size);
+        }
+        self.write_size(&label);
+
+        // The strings the entries refer to
+        for (i, root) in roots.iter().enumerate() {
+            self.write_label_declaration(&AsmLabel::new(format!(".Lroot_name_{}", i)));
+            self.write_bytes(root.name.as_bytes());
+            self.write_label_declaration(&AsmLabel::new(format!(".Lroot_type_{}", i)));
+            self.write_bytes(root.type_name.as_bytes());
+        }
+        self.start_directive(AsmDirective::Other);
+    }
+
+    // Writes the RODAL_FIXUPS table, that lists every stub that needs to be rebuilt
+    // (the layout of the table is given by FixupTable and FixupEntry in rebuild.rs)
+    fn write_fixup_table(&mut self) {
+        let label = AsmLabel::new("RODAL_FIXUPS".to_string());
+        let size = mem::size_of::<usize>() + self.fixups.len() * mem::size_of::<FixupEntry>();
+        self.write_global(&label);
+        self.write_type_object(&label);
+        self.write_size_align(size, mem::align_of::<FixupEntry>());
+        self.write_label_declaration(&label);
+
+        let fixups = mem::take(&mut self.fixups);
+        self.write_value(fixups.len());
+        for (i, (slot, type_name)) in fixups.iter().enumerate() {
+            self.write_label_reference(slot.clone());
+            self.write_label_reference(AsmLabel::new(format!(".Lfixup_type_{}", i)));
+            self.write_value(type_name.len());
+        }
+        self.write_size(&label);
+
+        for (i, (_, type_name)) in fixups.iter().enumerate() {
+            self.write_label_declaration(&AsmLabel::new(format!(".Lfixup_type_{}", i)));
+            self.write_bytes(type_name.as_bytes());
+        }
+        self.start_directive(AsmDirective::Other);
+    }
+
+    // Records that we've just declared the label of an object (if we're writing an object table)
+    fn record_object(&mut self, label: &AsmLabel, size: usize) {
+        if let Some(ref mut table) = self.object_table {
+            table.push(ObjectRecord {
+                label: label.clone(),
+                size,
+                type_name: String::new(),
+            });
+            self.naming_object = true;
+        }
+    }
+
+    // Called by debug_record, the first dump function called after record_object is the one dumping the object
+    #[inline]
+    fn record_object_type<T: ? Sized + Named>(&mut self, func_name: &str) {
+        if self.naming_object {
+            self.naming_object = false;
+            let type_name = if func_name == "dump" {
+                T::name()
+            } else {
+                format!("{}::{}", T::name(), func_name)
+            };
+            self.object_table.as_mut().unwrap().last_mut().unwrap().type_name = type_name;
+        }
+    }
+
+    // Writes the RODAL_OBJECTS table, that describes every object in the dump
+    // (the layout of the table is given by ObjectTable and ObjectEntry in protect.rs)
+    fn write_object_table(&mut self) {
+        let objects = match self.object_table.take() {
+            Some(objects) => objects,
+            None => return
+        };
+
+        let label = AsmLabel::new("RODAL_OBJECTS".to_string());
+        let size = mem::size_of::<usize>() + objects.len() * mem::size_of::<ObjectEntry>();
+        self.write_global(&label);
+        self.write_type_object(&label);
+        self.write_size_align(size, mem::align_of::<ObjectEntry>());
+        self.write_label_declaration(&label);
+
+        // The objects were written in order, so the table will be sorted by address
+        self.write_value(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            self.write_label_reference(object.label.clone());
+            self.write_value(object.size);
+            self.write_label_reference(AsmLabel::new(format!(".Lobject_type_{}", i)));
+            self.write_value(object.type_name.len());
+        }
+        self.write_size(&label);
+
+        for (i, object) in objects.iter().enumerate() {
+            self.write_label_declaration(&AsmLabel::new(format!(".Lobject_type_{}", i)));
+            self.write_bytes(object.type_name.as_bytes());
+        }
+        self.start_directive(AsmDirective::Other);
     }
 
     #[inline]
@@ -269,21 +543,37 @@ impl<W: Write> AsmDumper<W> {
         writeln!(self.file, "\t.skip {}", size).unwrap();
     }
 
-    #[inline]
-    fn write_byte(&mut self, value: u8) {
-        match self.current_directive {
-            // Continue the current byte directive
-            AsmDirective::Byte => {
-                write!(self.file, ", ").unwrap();
-            }
-            _ => {
-                self.start_directive(AsmDirective::Byte);
-                // Start a new byte directive
-                write!(self.file, "\t.byte ").unwrap();
+    // Each chunk of values is formatted into byte_buffer (using HEX_DIGITS) and then written in one go
+    // (as formatting each byte with write! is very slow)
+    fn write_bytes(&mut self, values: &[u8]) {
+        for chunk in values.chunks(BYTE_CHUNK_SIZE) {
+            self.byte_buffer.clear();
+            for &value in chunk {
+                match self.current_directive {
+                    // Continue the current byte directive
+                    AsmDirective::Byte => self.byte_buffer.extend_from_slice(b", "),
+                    _ => {
+                        // Start a new byte directive (the buffer is empty, as this must be the first byte of the chunk)
+                        self.start_directive(AsmDirective::Byte);
+                        self.byte_buffer.extend_from_slice(b"\t.byte ");
+                    }
+                }
+                self.byte_buffer.extend_from_slice(&[
+                    b'0',
+                    b'x',
+                    HEX_DIGITS[(value >> 4) as usize],
+                    HEX_DIGITS[(value & 0xf) as usize],
+                ]);
             }
+            self.file.write_all(&self.byte_buffer).unwrap();
         }
+    }
 
-        write!(self.file, "{:#02x}", value).unwrap();
+    #[inline]
+    // Write a pointer sized integer
+    fn write_value(&mut self, value: usize) {
+        self.start_directive(AsmDirective::Other);
+        writeln!(self.file, "\t{} {}", POINTER_DIRECTIVE, value).unwrap();
     }
 
     #[inline]
@@ -317,25 +607,29 @@ impl<W: Write> AsmDumper<W> {
     }
 
     #[inline]
-    // We need to write the size of objects so that we can handle it if
+    // We need to write the size and alignment of objects so that we can handle it if
     // realloc is called on one
     fn write_size_align(&mut self, size: usize, alignment: usize) {
-        // We need to align to usize as we will store a usize indicating the size of the object
+        // The alignment and then the size are stored (as usizes) immediatly before the object
+        let header_size = 2 * mem::size_of::<usize>();
+        // We need to align to usize as we will store usizes before the object
+        let object_alignment = alignment;
         let alignment = lcm(mem::align_of::<usize>(), alignment);
         self.start_directive(AsmDirective::Other);
         writeln!(self.file, "\t.balign {}", alignment).unwrap();
 
         // Add neccesary padding so that the data for the object is properly aligned
-        let padding = alignment - mem::size_of::<usize>();
+        let padding = (alignment - header_size % alignment) % alignment;
         if padding > 0 {
-            write!(self.file, "\t.skip {}", padding).unwrap();
+            writeln!(self.file, "\t.skip {}", padding).unwrap();
         }
 
-        // Write the size, which will be aligned to mem::align_of::<usize>()
+        // Write the alignment and size, which will be aligned to mem::align_of::<usize>()
+        writeln!(self.file, "\t{} {}", POINTER_DIRECTIVE, object_alignment).unwrap();
         writeln!(self.file, "\t{} {}", POINTER_DIRECTIVE, size).unwrap();
 
         // Now the next thing that is written will be aligned to alignment
-        // And have a properly aligned usize immediatly before it
+        // And have a properly aligned header immediatly before it
     }
 
     #[inline]
@@ -381,32 +675,11 @@ impl<W: Write> AsmDumper<W> {
 
     #[inline]
     fn get_object(&mut self, start: Address, size: usize, alignment: usize) -> Option<AsmLabel> {
-        let res = match &self.dumped_objects.get(&start) {
-            &Some(value) => Some(value.label.clone()),
-            &None => match &self.pending_objects.get(&start) {
-                &Some(value) => Some(value.label.clone()),
-                &None => match &self.dumping_objects.get(&start) {
-                    &Some(value) => Some(value.label.clone()),
-                    &None => None
-                }
-            }
-        };
+        let object = self.objects.get(&start);
 
         // Check that objects don't overlap
         debug_only!({
-            let object = if self.dumped_objects.contains_key(&start) {
-                self.dumped_objects.get(&start)
-            } else if self.pending_objects.contains_key(&start) {
-                self.pending_objects.get(&start)
-            } else if self.dumping_objects.contains_key(&start) {
-                // self.dumping_objects.contains_key(&start)
-                self.dumping_objects.get(&start)
-            } else {
-                None
-            };
-
-            if object.is_some() {
-                let object = object.unwrap();
+            if let Some(object) = object {
                 debug_assert!(
                     object.size == size && object.alignment == alignment,
                     "conflicting layouts for object [{}], got size = {} and {}, and alignment = {} and {}",
@@ -419,7 +692,7 @@ impl<W: Write> AsmDumper<W> {
             }
         });
 
-        res
+        object.map(|object| object.label.clone())
     }
 
     #[inline]
@@ -454,17 +727,14 @@ impl<W: Write> AsmDumper<W> {
         // Value is suposed to be a new complete object, so verify it does
         // not overlap with any other complete objects
         debug_only!({
-            /*debug_assert!(get_overlap(start, start+size, &mut self.dumped_objects).count() == 0,
-                "the object range [{}, {}) overlaps with a complete object", start, start+size);
-            debug_assert!(get_overlap(start, start+size, &mut self.pending_objects).count() == 0,
-                "the object range [{}, {}) overlaps with a complete object", start, start+size);
-            debug_assert!(get_overlap(start, start+size, &mut self.dumping_objects).count() == 0,
+            /*debug_assert!(get_overlap(start, start+size, &mut self.objects).count() == 0,
                 "the object range [{}, {}) overlaps with a complete object", start, start+size);*/
         });
-        self.pending_objects.insert(
+        self.objects.insert(
             start,
             ObjectInfo::new(value, dump, start, size, alignment, label.clone()),
         );
+        self.pending_objects.push_back(start);
         label
     }
 }
@@ -483,6 +753,43 @@ impl<W: Write> Dumper for AsmDumper<W> {
         }
         self.tags.insert(tag, vec![value]); // Add a new list
     }
+    fn forbid_references(&mut self, start: Address, end: Address, owner: String) {
+        if let Some(&ptr) = self.pending_references.range(start..end).next() {
+            panic_forbidden_reference(ptr, &owner);
+        }
+        self.forbidden.insert(start, (end, owner));
+    }
+    fn record_error(&mut self, message: String) {
+        self.errors.push(message);
+    }
+    fn record_fixup_here(&mut self, type_name: String) {
+        // Label the current position, so the loader can find the stub
+        let label = AsmLabel::new(format!(".Lfixup_{}", self.fixups.len()));
+        self.write_label_declaration(&label);
+        self.fixups.push((label, type_name));
+    }
+    fn keep_alive_raw(&mut self, value: *mut (), drop: unsafe fn(*mut ())) {
+        self.kept_alive.push((value, drop));
+    }
+    fn count_reference(&mut self, object: Address, weak: bool) {
+        if let Some(ref mut counts) = self.reference_counts {
+            let count = counts.entry(object).or_insert((0, 0));
+            if weak {
+                count.1 += 1;
+            } else {
+                count.0 += 1;
+            }
+        }
+    }
+    fn dump_reference_count_here(&mut self, object: Address, weak: bool, count: usize) {
+        if self.reference_counts.is_none() {
+            return self.dump_value_here(&count);
+        }
+        // The count isn't known until every handle has been dumped, so reference a symbol that finish will define
+        self.reference_counts.as_mut().unwrap().entry(object).or_insert((0, 0));
+        self.write_label_reference(reference_count_label(object, weak));
+        self.current_pointer += mem::size_of::<usize>();
+    }
     /// Record the given complete object as needing to be dumped (because it is referenced)
     fn reference_object_function_sized_position<T: ? Sized, P: ? Sized>(
         &mut self,
@@ -559,20 +866,19 @@ impl<W: Write> Dumper for AsmDumper<W> {
         ));
 
         //trace!("{:?}: dump_reference_here({:?} = &{})", self.current_pointer, Address::new(value), ptr);
+        if let Some((_, &(end, ref owner))) = self.forbidden.range(..=ptr).next_back() {
+            if ptr < end {
+                panic_forbidden_reference(ptr, owner);
+            }
+        }
 
         // Look for a recorded complete object containg this,..
-        let label = match get_complete_object(ptr, &self.dumped_objects) {
+        let label = match get_complete_object(ptr, &self.objects) {
             Some(value) => value.label.offset(ptr - value.start),
-            None => match get_complete_object(ptr, &self.pending_objects) {
-                Some(value) => value.label.offset(ptr - value.start),
-                None => match get_complete_object(ptr, &self.dumping_objects) {
-                    Some(value) => value.label.offset(ptr - value.start),
-                    None => {
-                        // Just create a temporary label, and record our pointer
-                        self.pending_references.insert(ptr);
-                        AsmLabel::new(format!(".Lptr_{}", ptr))
-                    }
-                }
+            None => {
+                // Just create a temporary label, and record our pointer
+                self.pending_references.insert(ptr);
+                AsmLabel::new(format!(".Lptr_{}", ptr))
             }
         };
 
@@ -587,9 +893,8 @@ impl<W: Write> Dumper for AsmDumper<W> {
         let value = Address::new(value);
         //trace!("{:?}: dump_value_sized_here({:?}, {})", self.current_pointer, value, size);
 
-        for i in 0..size {
-            // Write the byte
-            self.write_byte(*(value + i).to_ref::<u8>());
+        if size != 0 {
+            self.write_bytes(unsafe { std::slice::from_raw_parts(value.to_ref::<u8>(), size) });
         }
         self.current_pointer += size;
         //trace!("+ {} -> {:?}", size, self.current_pointer);
@@ -624,6 +929,12 @@ impl<W: Write> Dumper for AsmDumper<W> {
             func_name = func_name
         );
         self.debug_stack.push(self.current_pointer);
+        self.record_object_type::<T>(func_name);
+    }
+    #[cfg(not(debug_assertions))]
+    #[inline]
+    fn debug_record<T: ? Sized + Named>(&mut self, func_name: &str) {
+        self.record_object_type::<T>(func_name);
     }
 
     // Set the current position to be returned by current_position
@@ -655,7 +966,7 @@ impl<W: Write> Dumper for AsmDumper<W> {
 // Returns the range of elements in the map that overlaps with [start, end)
 // Only used to check invariants in debug mode
 /*#[cfg(debug_assertions)]
-fn get_overlap<'a, W: Write>(start: Address, end: Address, map: &'a mut HashMap<Address, ObjectInfo<W>>)
+fn get_overlap<'a, W: Write>(start: Address, end: Address, map: &'a mut BTreeMap<Address, ObjectInfo<W>>)
                       -> RangeMut<'a, Address, ObjectInfo<W>> {
 
     let start = match map.range_mut(..start).last() {
@@ -667,17 +978,20 @@ fn get_overlap<'a, W: Write>(start: Address, end: Address, map: &'a mut HashMap<
     map.range_mut(start..end)
 }*/
 
+// The symbol used for the strong or weak count of the counted object at 'object'
+fn reference_count_label(object: Address, weak: bool) -> AsmLabel {
+    AsmLabel::new(format!(".L{}_count_{}", if weak { "weak" } else { "strong" }, object))
+}
+
 // Gets the complete object that contains start
-fn get_complete_object<'a, W: Write>(_: Address, _: &'a HashMap<Address, ObjectInfo<W>>) -> Option<&'a ObjectInfo<W>> {
-    unimplemented!();
-    /*match map.range((Bound::Unbounded, Bound::Included(start))).last() {
-        Some((_, value)) => {
-            if value.start + value.size > start {
-                Some(value)
-            } else {
-                None
-            }
-        }
-        None => None
-    }*/
+// (the last object that starts at or before it, provided it starts at, or extends past, start)
+fn panic_forbidden_reference(ptr: Address, owner: &str) -> ! {
+    panic!("can't dump a reference to {}, as it's inside the elements of a {} (which are moved when it's rebuilt)", ptr, owner)
+}
+
+fn get_complete_object<W: Write>(start: Address, map: &BTreeMap<Address, ObjectInfo<W>>) -> Option<&ObjectInfo<W>> {
+    match map.range(..=start).next_back() {
+        Some((_, value)) if value.start == start || start < value.start + value.size => Some(value),
+        _ => None
+    }
 }
@@ -14,40 +14,603 @@
 
 extern crate libc;
 
-use std::collections::HashMap;
-use std::ffi::CString;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::fmt;
 use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
 use std::ptr;
+use std::sync::Once;
+use std::sync::atomic::{fence, Ordering};
 use super::*;
 
+/// The reasons loading a root from a rodal dump can fail
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadError {
+    /// dlopen couldn't open the object to search for symbols in (contains the message from dlerror)
+    DlopenFailed(String),
+    /// The dump has no object at a symbol with the given name
+    MissingSymbol(String),
+    /// The symbol exists, but the object dumped there dosn't have the layout of the requested type
+    TypeMismatch {
+        name: String,
+        expected_size: usize,
+        found_size: usize,
+        alignment: usize,
+    },
+    /// The root has already been loaded mutably, or is being loaded mutably after it was already loaded
+    AlreadyBorrowed(String),
+    /// The dump contains a stub for a type that wasn't registered with the Rebuilders
+    MissingRebuild(String),
+    /// The fixups of the dump have already been applied
+    AlreadyRebuilt,
+    /// The dump contains stubs that haven't been rebuilt yet (so nothing in it can be loaded until they are)
+    FixupsNotApplied,
+    /// mprotect failed when write protecting a dump (contains the error message)
+    ProtectFailed(String),
+    /// All MAX_RODAL_IMAGES slots for image bounds are already in use
+    TooManyImages,
+    /// The dump dosn't start with RODAL_MAGIC (so it isn't a dump, or was written by a very old rodal)
+    NotADump,
+    /// The dump was written in a different format version to RODAL_FORMAT_VERSION (contains the dump's version)
+    UnsupportedVersion(usize),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::DlopenFailed(ref message) => write!(f, "dlopen failed: {}", message),
+            LoadError::MissingSymbol(ref name) => write!(f, "no rodal root named '{}'", name),
+            LoadError::TypeMismatch {
+                ref name,
+                expected_size,
+                found_size,
+                alignment,
+            } => write!(
+                f,
+                "rodal root '{}' has size {}, but the requested type has size {} (and alignment {})",
+                name,
+                found_size,
+                expected_size,
+                alignment
+            ),
+            LoadError::AlreadyBorrowed(ref name) => {
+                write!(f, "rodal root '{}' is already borrowed in a conflicting way", name)
+            }
+            LoadError::MissingRebuild(ref name) => write!(f, "no Rebuild impl registered for '{}'", name),
+            LoadError::AlreadyRebuilt => write!(f, "the fixups of the dump have already been applied"),
+            LoadError::FixupsNotApplied => write!(f, "the fixups of the dump haven't been applied"),
+            LoadError::ProtectFailed(ref message) => write!(f, "couldn't write protect dump: {}", message),
+            LoadError::TooManyImages => write!(f, "can't load more than {} rodal images", MAX_RODAL_IMAGES),
+            LoadError::NotADump => write!(f, "RODAL_START isn't the start of a rodal dump"),
+            LoadError::UnsupportedVersion(version) => write!(
+                f,
+                "the dump has format version {}, but this version of rodal loads format version {}",
+                version,
+                RODAL_FORMAT_VERSION
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {}
+
+// Returns the message of the last dl* error (and clears it)
+fn last_dlerror() -> Option<String> {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(message) }.to_string_lossy().into_owned())
+    }
+}
+
+static RTLD_DEFAULT_INIT: Once = Once::new();
+static mut RTLD_DEFAULT: Option<Result<usize, LoadError>> = None;
+
+// The handle for the main program, this is only opened once
+fn rtld_default() -> Result<*mut libc::c_void, LoadError> {
+    RTLD_DEFAULT_INIT.call_once(|| unsafe {
+        let handle = libc::dlopen(ptr::null(), libc::RTLD_LAZY);
+        RTLD_DEFAULT = Some(if handle.is_null() {
+            Err(LoadError::DlopenFailed(last_dlerror().unwrap_or_default()))
+        } else {
+            Ok(handle as usize)
+        });
+    });
+    match *unsafe { &*ptr::addr_of!(RTLD_DEFAULT) } {
+        Some(Ok(handle)) => Ok(handle as *mut libc::c_void),
+        Some(Err(ref err)) => Err(err.clone()),
+        None => unreachable!()
+    }
+}
+
+// Looks up the symbol 'name' in the object refered to by 'handle'
+fn lookup_symbol(handle: *mut libc::c_void, name: &str) -> Result<Address, LoadError> {
+    let cstring = CString::new(name).map_err(|_| LoadError::MissingSymbol(name.to_string()))?;
+
+    // Clear any old error, so we know that any error afterwards was caused by dlsym
+    last_dlerror();
+    let ret = unsafe { libc::dlsym(handle, cstring.as_ptr()) };
+    if last_dlerror().is_some() || ret.is_null() {
+        Err(LoadError::MissingSymbol(name.to_string()))
+    } else {
+        Ok(Address::from_ptr(ret))
+    }
+}
+
+// The [start, end) bounds of the dump in 'handle' (given by the RODAL_START and RODAL_END labels)
+fn dump_bounds(handle: *mut libc::c_void) -> Result<(Address, Address), LoadError> {
+    Ok((lookup_symbol(handle, "RODAL_START")?, lookup_symbol(handle, "RODAL_END")?))
+}
+
+// Checks that the dump starting at 'start' is in the format we load
+// (the dumper writes RODAL_MAGIC and then RODAL_FORMAT_VERSION at RODAL_START)
+fn check_format(start: Address) -> Result<(), LoadError> {
+    let header = start.to_ref::<[usize; 2]>();
+    if header[0] != RODAL_MAGIC {
+        Err(LoadError::NotADump)
+    } else if header[1] != RODAL_FORMAT_VERSION {
+        Err(LoadError::UnsupportedVersion(header[1]))
+    } else {
+        Ok(())
+    }
+}
+
+// Finds the root called 'name' in the dump in 'handle', and checks that it could be a T
+// (roots are looked up in the dump's root directory, falling back to their symbol
+// for dumps without one, and for objects that aren't roots, e.g. the dump's tables)
+fn find_root<T>(handle: *mut libc::c_void, name: &str) -> Result<*mut T, LoadError> {
+    check_fixups_applied(handle)?;
+    match Roots::find(handle).ok().and_then(|roots| roots.get(name)) {
+        Some(root) => check_layout::<T>(name, root.address, root.size),
+        None => find_symbol::<T>(handle, name)
+    }
+}
+
+// Finds the object dumped at the symbol 'name' in 'handle', and checks that it could be a T
+// (the dumper writes the size of every object immediately before it, when the dump's bounds are known
+// this is only read for symbols strictly inside them, as RODAL_START and RODAL_END aren't objects)
+fn find_symbol<T>(handle: *mut libc::c_void, name: &str) -> Result<*mut T, LoadError> {
+    let address = lookup_symbol(handle, name)?;
+    if let Ok((start, end)) = dump_bounds(handle) {
+        if address <= start || address >= end {
+            return Err(LoadError::MissingSymbol(name.to_string()));
+        }
+    }
+    let found_size = *(address - mem::size_of::<usize>()).to_ref::<usize>();
+    check_layout::<T>(name, address, found_size)
+}
+
+// Checks that the object of size 'found_size' dumped at 'address' could be a T
+fn check_layout<T>(name: &str, address: Address, found_size: usize) -> Result<*mut T, LoadError> {
+    if found_size != mem::size_of::<T>() || address.value() & (mem::align_of::<T>() - 1) != 0 {
+        Err(LoadError::TypeMismatch {
+            name: name.to_string(),
+            expected_size: mem::size_of::<T>(),
+            found_size,
+            alignment: mem::align_of::<T>(),
+        })
+    } else {
+        Ok(address.to_ptr::<T>() as *mut T)
+    }
+}
+
+/// Finds the root called 'name' in the main program, and checks that it could be a T
+pub fn find_asm_name<T>(name: &str) -> Result<*mut T, LoadError> {
+    find_root::<T>(rtld_default()?, name)
+}
+
+/// Options for loading a dump
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoadOptions<'a> {
+    /// Make the dump read only, and report the first write to it (for debugging)
+    /// Anything that needs to modify the dump in place (including load_asm_name_mut) will crash,
+    /// this includes writes that std makes through shared references to dumped objects, e.g.:
+    /// cloning or dropping an Rc or Arc (which changes its reference counts), Mutex::lock or RwLock::write,
+    /// Cell::set or RefCell::borrow_mut, and initialising a OnceCell or OnceLock
+    pub write_protect: bool,
+    /// Apply the dump's fixups with these rebuilders while loading it
+    /// (a write protected dump can't be modified afterwards, so its fixups are always applied before it's protected)
+    pub rebuilders: Option<&'a Rebuilders>,
+}
+
+// Applies the fixups and then the write protection requested by 'options' to the dump [start, end) in 'handle'
+unsafe fn apply_load_options(
+    handle: *mut libc::c_void,
+    start: Address,
+    end: Address,
+    options: LoadOptions,
+) -> Result<(), LoadError> {
+    if options.write_protect || options.rebuilders.is_some() {
+        // If the dump has stubs and no rebuilders were given, this fails with MissingRebuild
+        apply_fixups(handle, options.rebuilders.unwrap_or(&Rebuilders::new()))?;
+    }
+    if options.write_protect {
+        protect_region(start, end, find_object_table(handle))
+    } else {
+        Ok(())
+    }
+}
+
+/// Records the bounds of the main program's dump (panicking if it's not in a format we load)
+///
+/// # Safety
+/// [start, end) must be the bounds of the main program's dump, and nothing may be using the dump yet
 pub unsafe fn load_asm_bounds(start: Address, end: Address) {
+    if let Err(err) = check_format(start) {
+        panic!("{}", err);
+    }
     RODAL_BOUND = Some((start, end));
 }
 
+/// Like load_asm_bounds, but applies the given options to the main program's dump
+///
+/// # Safety
+/// [start, end) must be the bounds of the main program's dump, and nothing may be using the dump yet
+pub unsafe fn load_asm_bounds_with_options(start: Address, end: Address, options: LoadOptions) -> Result<(), LoadError> {
+    check_format(start)?;
+    load_asm_bounds(start, end);
+    apply_load_options(rtld_default()?, start, end, options)
+}
+
+// Returns the RODAL_OBJECTS table, or null if the dump dosn't have one
+fn find_object_table(handle: *mut libc::c_void) -> *const ObjectTable {
+    lookup_symbol(handle, "RODAL_OBJECTS")
+        .map(|address| address.to_ptr::<ObjectTable>())
+        .unwrap_or(ptr::null())
+}
+
 pub unsafe fn load_asm_pointer_move<'a, T>(ptr: *mut T) -> T {
     ptr::read(ptr)
 }
 
+/// Like load_asm_name_move, but reports why the root couldn't be loaded instead of panicking
+pub fn load_asm_name_move_checked<T>(name: &str) -> Result<T, LoadError> {
+    find_asm_name::<T>(name).map(|ptr| unsafe { ptr::read(ptr) })
+}
+
 pub fn try_load_asm_name_move<T>(name: &str) -> Option<T> {
-    let rtld_default = unsafe { libc::dlopen(ptr::null(), 0) };
-    let cstring = CString::new(name.to_string());
-    let ret = unsafe { libc::dlsym(rtld_default, cstring.unwrap().as_ptr()) };
-    if unsafe { libc::dlerror().is_null() } || ret.is_null() {
-        None
-    } else {
-        Some(unsafe { ptr::read(mem::transmute::<*const libc::c_void, *mut T>(ret)) })
-    }
+    load_asm_name_move_checked(name).ok()
 }
 
 pub fn load_asm_name_move<T>(name: &str) -> T {
-    let rtld_default = unsafe { libc::dlopen(ptr::null(), 0) };
-    let cstring = CString::new(name.to_string());
-    let ret = unsafe { libc::dlsym(rtld_default, cstring.unwrap().as_ptr()) };
-    unsafe { ptr::read(mem::transmute::<*const libc::c_void, *mut T>(ret)) }
+    match load_asm_name_move_checked(name) {
+        Ok(value) => value,
+        Err(err) => panic!("{}", err)
+    }
+}
+
+/// Like load_asm_name_ref, but reports why the root couldn't be loaded instead of panicking
+pub fn load_asm_name_ref_checked<T>(name: &str) -> Result<&'static T, LoadError> {
+    let ptr = find_asm_name::<T>(name)?;
+    lend_root(name, Address::from_ptr(ptr), false)?;
+    Ok(unsafe { &*ptr })
+}
+
+/// Returns a reference to the root called 'name', so it can be used in place
+/// (unlike load_asm_name_move, this never creates a second owner of the root)
+pub fn load_asm_name_ref<T>(name: &str) -> &'static T {
+    match load_asm_name_ref_checked(name) {
+        Ok(value) => value,
+        Err(err) => panic!("{}", err)
+    }
+}
+
+/// Like load_asm_name_mut, but reports why the root couldn't be loaded instead of panicking
+pub fn load_asm_name_mut_checked<T>(name: &str) -> Result<&'static mut T, LoadError> {
+    let ptr = find_asm_name::<T>(name)?;
+    lend_root(name, Address::from_ptr(ptr), true)?;
+    Ok(unsafe { &mut *ptr })
+}
+
+/// Returns a mutable reference to the root called 'name', so it can be used in place
+/// This can only succeed once for each root (and never after it has been loaded with load_asm_name_ref)
+pub fn load_asm_name_mut<T>(name: &str) -> &'static mut T {
+    match load_asm_name_mut_checked(name) {
+        Ok(value) => value,
+        Err(err) => panic!("{}", err)
+    }
+}
+
+/// Returns an iterator over every root in the main program's dump
+pub fn load_asm_roots() -> Result<Roots, LoadError> {
+    Roots::find(rtld_default()?)
+}
+
+/// Rebuilds every stub in the main program's dump (see Rebuild)
+/// This must be called (once) before anything in the dump is used
+pub fn apply_asm_fixups(rebuilders: &Rebuilders) -> Result<(), LoadError> {
+    apply_fixups(rtld_default()?, rebuilders)
 }
 
+/// Loads the tags dumped by AsmDumper::dump_tags
+/// (the tags are a HashMap, so the fixups must have been applied first, Rebuilders::new registers their type)
 pub fn load_asm_tags<'a>() -> HashMap<usize, Vec<*const ()>> {
     load_asm_name_move("RODAL_TAGS")
 }
 
+/// A dump that lives in a shared library (loaded with dlopen), instead of the main program
+/// Roots are looked up in the library itself, and the allocator shim will treat the library's dump
+/// the same way as the main program's.
+/// The library is never unloaded unless you explicitly call close (as objects may still refer to it)
+pub struct Image {
+    handle: *mut libc::c_void,
+    start: Address,
+    end: Address,
+    // The index of our bounds in RODAL_IMAGE_BOUNDS
+    slot: usize,
+}
+
+// The handle is only ever passed to dlsym and dlclose, which are thread safe
+unsafe impl Send for Image {}
+unsafe impl Sync for Image {}
+
+impl Image {
+    /// Opens the shared library at 'path', and registers the bounds of its dump
+    /// (given by the RODAL_START and RODAL_END labels the AsmDumper emits)
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Image, LoadError> {
+        Image::open_with_options(path, LoadOptions::default())
+    }
+
+    /// Like open, but also applies 'options' (see LoadOptions)
+    pub fn open_with_options<P: AsRef<Path>>(path: P, options: LoadOptions) -> Result<Image, LoadError> {
+        let path = path.as_ref();
+        let cstring = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| LoadError::DlopenFailed(format!("{} contains a nul byte", path.display())))?;
+
+        // Use RTLD_LOCAL, so that our symbols (in particular RODAL_START and RODAL_END)
+        // don't conflict with those of the main program or other images
+        let handle = unsafe { libc::dlopen(cstring.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        if handle.is_null() {
+            return Err(LoadError::DlopenFailed(last_dlerror().unwrap_or_default()));
+        }
+
+        let bounds = dump_bounds(handle)
+            .and_then(|(start, end)| check_format(start).map(|_| (start, end)))
+            .and_then(|(start, end)| unsafe { register_image_bounds(start, end) }.map(|slot| (start, end, slot)));
+        let image = bounds.map(|(start, end, slot)| Image {
+            handle,
+            start,
+            end,
+            slot,
+        });
+        match image {
+            Ok(image) => {
+                if let Err(err) = unsafe { apply_load_options(handle, image.start, image.end, options) } {
+                    unsafe { image.close() };
+                    return Err(err);
+                }
+                Ok(image)
+            }
+            Err(err) => {
+                unsafe { libc::dlclose(handle) };
+                Err(err)
+            }
+        }
+    }
+
+    /// The [start, end) range of the image's dump
+    pub fn bounds(&self) -> (Address, Address) {
+        (self.start, self.end)
+    }
+
+    /// Finds the root called 'name' in this image, and checks that it could be a T
+    pub fn find_name<T>(&self, name: &str) -> Result<*mut T, LoadError> {
+        find_root::<T>(self.handle, name)
+    }
+
+    pub fn load_name_move<T>(&self, name: &str) -> Result<T, LoadError> {
+        self.find_name::<T>(name).map(|ptr| unsafe { ptr::read(ptr) })
+    }
+
+    pub fn load_name_ref<T>(&self, name: &str) -> Result<&'static T, LoadError> {
+        let ptr = self.find_name::<T>(name)?;
+        lend_root(name, Address::from_ptr(ptr), false)?;
+        Ok(unsafe { &*ptr })
+    }
+
+    pub fn load_name_mut<T>(&self, name: &str) -> Result<&'static mut T, LoadError> {
+        let ptr = self.find_name::<T>(name)?;
+        lend_root(name, Address::from_ptr(ptr), true)?;
+        Ok(unsafe { &mut *ptr })
+    }
+
+    /// Returns an iterator over every root in this image
+    pub fn roots(&self) -> Result<Roots, LoadError> {
+        Roots::find(self.handle)
+    }
+
+    /// Rebuilds every stub in this image (see Rebuild)
+    /// This must be called (once) before anything in the image is used
+    pub fn apply_fixups(&self, rebuilders: &Rebuilders) -> Result<(), LoadError> {
+        apply_fixups(self.handle, rebuilders)
+    }
+
+    /// Loads the tags dumped by AsmDumper::dump_tags (like load_asm_tags, this requires the fixups to be applied)
+    pub fn load_tags(&self) -> Result<HashMap<usize, Vec<*const ()>>, LoadError> {
+        self.load_name_move("RODAL_TAGS")
+    }
+
+    /// Unregisters the image's bounds and unloads the library
+    ///
+    /// # Safety
+    /// Nothing may refer to the image's dump after this is called
+    /// (including any references returned by load_name_ref and load_name_mut)
+    pub unsafe fn close(self) {
+        let mut loans = ROOT_LOANS.lock().unwrap();
+        let lent: Vec<Address> = loans.range(self.start..self.end).map(|(&address, _)| address).collect();
+        for address in lent {
+            loans.remove(&address);
+        }
+        unprotect_region(self.start);
+        unregister_image_bounds(self.slot);
+        libc::dlclose(self.handle);
+    }
+}
+
+// The layout of the RODAL_ROOTS table written by the AsmDumper
+#[repr(C)]
+pub(crate) struct RootTable {
+    len: usize,
+    entries: [RootEntry; 0],
+}
+#[repr(C)]
+pub(crate) struct RootEntry {
+    name: *const u8,
+    name_len: usize,
+    address: *const (),
+    type_name: *const u8,
+    type_name_len: usize,
+    size: usize,
+}
+
+/// Describes one of the roots of a dump
+#[derive(Clone, Copy, Debug)]
+pub struct RootInfo {
+    /// The name the root was dumped with (and the name of its symbol)
+    pub name: &'static str,
+    pub address: Address,
+    /// The Named::name of the type of the root
+    pub type_name: &'static str,
+    pub size: usize,
+}
+
+/// An iterator over the roots in a dump (in order of their names)
+pub struct Roots(std::slice::Iter<'static, RootEntry>);
+
+impl Roots {
+    fn find(handle: *mut libc::c_void) -> Result<Roots, LoadError> {
+        let table = lookup_symbol(handle, "RODAL_ROOTS")?.to_ref::<RootTable>();
+        Ok(Roots(
+            unsafe { std::slice::from_raw_parts(table.entries.as_ptr(), table.len) }.iter()
+        ))
+    }
+    // Finds the remaining root called 'name' (the table is sorted by name, so this is a binary search)
+    fn get(&self, name: &str) -> Option<RootInfo> {
+        let entries = self.0.as_slice();
+        entries
+            .binary_search_by(|entry| unsafe { table_str(entry.name, entry.name_len) }.cmp(name))
+            .ok()
+            .map(|index| root_info(&entries[index]))
+    }
+}
+
+// The strings in the table were written from rust Strings, so they are valid UTF-8
+unsafe fn table_str(ptr: *const u8, len: usize) -> &'static str {
+    std::str::from_utf8_unchecked(std::slice::from_raw_parts(ptr, len))
+}
+
+fn root_info(entry: &RootEntry) -> RootInfo {
+    unsafe {
+        RootInfo {
+            name: table_str(entry.name, entry.name_len),
+            address: Address::from_ptr(entry.address),
+            type_name: table_str(entry.type_name, entry.type_name_len),
+            size: entry.size,
+        }
+    }
+}
+
+impl Iterator for Roots {
+    type Item = RootInfo;
+    fn next(&mut self) -> Option<RootInfo> {
+        self.0.next().map(root_info)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+// Records [start, end) as a dump that the allocator shim should ignore, returning the slot it was stored in
+unsafe fn register_image_bounds(start: Address, end: Address) -> Result<usize, LoadError> {
+    let _guard = IMAGE_BOUNDS_LOCK.lock().unwrap();
+    let bounds = &mut *ptr::addr_of_mut!(RODAL_IMAGE_BOUNDS);
+    match bounds.iter().position(|bound| bound.is_none()) {
+        Some(slot) => {
+            bounds[slot] = Some((start, end));
+            // Make sure other threads see the new bounds before anything from the image is used
+            fence(Ordering::SeqCst);
+            Ok(slot)
+        }
+        None => Err(LoadError::TooManyImages)
+    }
+}
+
+unsafe fn unregister_image_bounds(slot: usize) {
+    let _guard = IMAGE_BOUNDS_LOCK.lock().unwrap();
+    (*ptr::addr_of_mut!(RODAL_IMAGE_BOUNDS))[slot] = None;
+    fence(Ordering::SeqCst);
+}
+
+// The fixup tables that have already been applied
+static APPLIED_FIXUPS: std::sync::Mutex<BTreeSet<Address>> = std::sync::Mutex::new(BTreeSet::new());
+
+// Fails if the dump in 'handle' contains stubs that haven't been rebuilt yet
+// (a root may contain, or refer to, any of them, and a stub can't be used in place of the real object)
+fn check_fixups_applied(handle: *mut libc::c_void) -> Result<(), LoadError> {
+    let table = match lookup_symbol(handle, "RODAL_FIXUPS") {
+        Ok(table) => table,
+        Err(_) => return Ok(()) // The dump was made without fixups
+    };
+    if table.to_ref::<FixupTable>().len == 0 || APPLIED_FIXUPS.lock().unwrap().contains(&table) {
+        Ok(())
+    } else {
+        Err(LoadError::FixupsNotApplied)
+    }
+}
+
+fn apply_fixups(handle: *mut libc::c_void, rebuilders: &Rebuilders) -> Result<(), LoadError> {
+    let table = match lookup_symbol(handle, "RODAL_FIXUPS") {
+        Ok(table) => table,
+        Err(_) => return Ok(()) // The dump was made without fixups
+    };
+    let table_ref = table.to_ref::<FixupTable>();
+    let entries = unsafe { std::slice::from_raw_parts(table_ref.entries.as_ptr(), table_ref.len) };
+
+    // Find every rebuild function first, so we don't rebuild anything if one is missing
+    let mut rebuilds = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let type_name = unsafe { table_str(entry.type_name, entry.type_name_len) };
+        match rebuilders.get(type_name) {
+            Some(rebuild) => rebuilds.push((entry.slot, rebuild)),
+            None => return Err(LoadError::MissingRebuild(type_name.to_string()))
+        }
+    }
+
+    let mut applied = APPLIED_FIXUPS.lock().unwrap();
+    if !applied.insert(table) {
+        return Err(LoadError::AlreadyRebuilt);
+    }
+    // Stubs that are contained in the contents of another stub are always recorded after it,
+    // and need to be rebuilt before it (as rebuilding it will move them)
+    for &(slot, rebuild) in rebuilds.iter().rev() {
+        unsafe { rebuild(slot) };
+    }
+    Ok(())
+}
+
+// The roots we've returned references to, and whether the reference was mutable
+static ROOT_LOANS: std::sync::Mutex<BTreeMap<Address, bool>> = std::sync::Mutex::new(BTreeMap::new());
+
+// Records that the root at 'address' has been lent out, failing if this conflicts with an earlier loan
+fn lend_root(name: &str, address: Address, mutable: bool) -> Result<(), LoadError> {
+    let mut loans = ROOT_LOANS.lock().unwrap();
+    match loans.get(&address) {
+        Some(&previous_mutable) if previous_mutable || mutable => Err(LoadError::AlreadyBorrowed(name.to_string())),
+        _ => {
+            loans.insert(address, mutable);
+            Ok(())
+        }
+    }
+}
+
 pub static mut RODAL_BOUND: Option<(Address, Address)> = None;
+
+pub const MAX_RODAL_IMAGES: usize = 16;
+/// The bounds of each loaded Image (these are only read by the allocator shim)
+pub static mut RODAL_IMAGE_BOUNDS: [Option<(Address, Address)>; MAX_RODAL_IMAGES] = [None; MAX_RODAL_IMAGES];
+// Serialises registering and unregistering images
+static IMAGE_BOUNDS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
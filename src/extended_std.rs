@@ -35,13 +35,14 @@ impl<'a, T: Dump> FakeArc<'a, T> {
         dumper.debug_record::<Self>("dump_inner");
 
         let start = dumper.current_position();
-        // Dump the default strong value of 1
+        let fake_inner = Address::new(self.inner) - offset_of!(rust_std::ArcInner<T> => data).get_byte_offset();
+        // Dump the default strong value of 1 (or the number of FakeArc's to inner, if the dumper is recounting references)
         dumper.dump_padding((start + offset_of!(rust_std::ArcInner<T> => strong).get_byte_offset()).to_ref::<()>());
-        dumper.dump_object_here(&std::sync::atomic::AtomicUsize::new(1));
+        dumper.dump_reference_count_here(fake_inner, false, 1);
 
         // Dump the default weak value of 1
         dumper.dump_padding((start + offset_of!(rust_std::ArcInner<T> => weak).get_byte_offset()).to_ref::<()>());
-        dumper.dump_object_here(&std::sync::atomic::AtomicUsize::new(1));
+        dumper.dump_reference_count_here(fake_inner, true, 1);
 
         // Dump the containing data
         dumper.dump_padding((start + offset_of!(rust_std::ArcInner<T> => data).get_byte_offset()).to_ref::<()>());
@@ -58,6 +59,7 @@ unsafe impl<'a, T: Dump> Dump for FakeArc<'a, T> {
         let fake_inner = (Address::new(self.inner) - offset_of!(rust_std::ArcInner<T> => data).get_byte_offset())
             .to_ref::<rust_std::ArcInner<T>>();
 
+        dumper.count_reference(Address::new(fake_inner), false);
         dumper.dump_padding(&self.inner);
         dumper.dump_reference_object_function_sized_position_here(
             self, // the argument to pass to the dump function
@@ -72,39 +74,31 @@ unsafe impl<'a, T: Dump> Dump for FakeArc<'a, T> {
     }
 }
 
-pub struct EmptyHashMap<K, V, S = std::collections::hash_map::RandomState>(rust_std::HashMap<K, V, S>);
+// Dumps as an empty std::collections::HashMap (it is rebuilt like any other HashMap when loaded)
+pub struct EmptyHashMap<K, V, S = std::collections::hash_map::RandomState>(std::collections::HashMap<K, V, S>);
 
 impl<K: Eq + std::hash::Hash, V> EmptyHashMap<K, V, std::collections::hash_map::RandomState> {
     pub fn new() -> Self {
-        unsafe {
-            std::mem::transmute(std::collections::HashMap::<K, V, std::collections::hash_map::RandomState>::new())
-        }
+        EmptyHashMap(std::collections::HashMap::new())
     }
 }
 
-// TODO: Rustc complains with 'transmute called with differently sized types: std::collections::HashMap<K, V, S> (size can vary because of S) to dump_std::EmptyHashMap<K, V, S> (size can vary because of S)'
-/*impl<K: Eq + std::hash::Hash, V, S: std::hash::BuildHasher> EmptyHashMap<K, V, S> {
-    fn with_hasher(hash_builder: S) -> Self { unsafe {std::mem::transmute(std::collections::HashMap::<K, V, S>::with_hasher(hash_builder))} }
-}*/
+impl<K: Eq + std::hash::Hash, V, S: std::hash::BuildHasher> EmptyHashMap<K, V, S> {
+    pub fn with_hasher(hash_builder: S) -> Self {
+        EmptyHashMap(std::collections::HashMap::with_hasher(hash_builder))
+    }
+}
 
 // The Eq + Hash and BuildHasher contstratins are needed
 // as almost all of the hashmap's code requires this
 // (without them, we won't even be able to iterate over it's elements)
-rodal_named!([K: Eq + std::hash::Hash + Named, V: Named, S: std::hash::BuildHasher + Dump] EmptyHashMap<K, V, S> [type_name!("rodal::EmptyHashMap<{}, {}, {}>", K, V, S)]);
-unsafe impl<K: Eq + std::hash::Hash + Named, V: Named, S: std::hash::BuildHasher + Dump> Dump
+rodal_named!([K: Eq + std::hash::Hash + Named, V: Named, S: std::hash::BuildHasher + Named] EmptyHashMap<K, V, S> [type_name!("rodal::EmptyHashMap<{}, {}, {}>", K, V, S)]);
+unsafe impl<K: Eq + std::hash::Hash + Dump, V: Dump, S: std::hash::BuildHasher + Dump> Dump
 for EmptyHashMap<K, V, S>
 {
     fn dump<D: ? Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
-        dumper.dump_object(&self.0.hash_builder);
-
-        // Dump table
-        dumper.dump_object(&self.0.table.capacity_mask);
-        dumper.dump_object(&self.0.table.size);
-        assert!(self.0.table.capacity() == 0);
-        // Not an actual pointer (there is no associated memory)
-        dumper.dump_value(&self.0.table.hashes);
-        dumper.dump_object(&self.0.resize_policy);
+        dumper.dump_object_here(&self.0);
     }
 }
 
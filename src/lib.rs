@@ -19,12 +19,19 @@ extern crate libc;
 #[cfg(debug_assertions)]
 extern crate log;
 extern crate num;
+#[cfg(feature = "derive")]
+extern crate rodal_derive;
 
 pub use address::*;
 pub use alloc::*;
 pub use asm_dumper::*;
 pub use asm_loader::*;
 pub use extended_std::*;
+pub use rebuild::*;
+pub use rust_std::{check_std_layouts, dump_fresh_except};
+#[cfg(feature = "derive")]
+pub use rodal_derive::{Dump, Named};
+use protect::*;
 use std::collections::BTreeMap;
 use std::mem;
 
@@ -34,6 +41,8 @@ mod asm_dumper;
 mod asm_loader;
 mod alloc;
 mod address;
+mod protect;
+mod rebuild;
 mod rust_std;
 mod extended_std;
 
@@ -51,6 +60,13 @@ pub unsafe trait Dump: Named {
     /// (use dump_object if you want to dump an object whilst dumping another one
     /// or use the Dumper's provided methods to start a dump)
     fn dump<D: ? Sized + Dumper>(&self, dumper: &mut D);
+
+    /// True if this type contains no pointers (or anything else that needs to be dumped specially),
+    /// and so can be dumped as a raw sequence of bytes (as can a contiguous sequence of them)
+    /// This is true for primitives (and any other type dumped with rodal_value!),
+    /// other types may opt in by setting it
+    /// (containers use it to dump all their elements with a single dump_value, see Dumper::dump_slice)
+    const NO_POINTERS: bool = false;
 }
 
 #[inline]
@@ -107,6 +123,42 @@ pub trait Dumper {
         self.tag_reference::<()>(value, tag);
     }
 
+    // Records that the object being dumped at the current position is a stub, that must be replaced
+    // when the dump is loaded by the Rebuild impl for the type named 'type_name'
+    fn record_fixup_here(&mut self, type_name: String);
+
+    // Records that nothing may refer to [start, end), as the objects there are moved when the dump is loaded
+    // (e.g. the elements of a stub's 'owner'), any reference to it will panic instead of dangling
+    fn forbid_references(&mut self, start: Address, end: Address, owner: String);
+
+    // Records that a value couldn't be dumped faithfully (e.g. because it was in use), the dump carries on,
+    // but finishing it will report 'message' (which should name the value's type)
+    fn record_error(&mut self, message: String);
+
+    // Takes ownership of 'value' (which 'drop' destroys), keeping it at the same address until the dumper is dropped
+    fn keep_alive_raw(&mut self, value: *mut (), drop: unsafe fn(*mut ()));
+    // Moves value to the heap and keeps it alive until the dumper is dropped, for values that are
+    // dumped in place of another (as any objects they own may not be dumped until the dump is finished)
+    #[inline]
+    fn keep_alive<T>(&mut self, value: T) -> *const T {
+        let value = Box::into_raw(Box::new(value));
+        self.keep_alive_raw(value as *mut (), drop_box::<T>);
+        value
+    }
+
+    // Records that a reference counted handle (e.g. an Arc, Rc or Weak) pointing to
+    // the counted object at 'object' has been dumped ('weak' is whether the handle is a Weak)
+    fn count_reference(&mut self, object: Address, weak: bool);
+
+    // Dumps the strong (or weak) count of the counted object at 'object', whose current value is 'count'
+    // (if the dumper is recounting references, it dumps the number of handles recorded by count_reference instead)
+    fn dump_reference_count_here(&mut self, object: Address, weak: bool, count: usize);
+    #[inline]
+    fn dump_reference_count<T: ? Sized>(&mut self, position: &T, object: Address, weak: bool, count: usize) {
+        self.dump_padding(position);
+        self.dump_reference_count_here(object, weak, count);
+    }
+
     // Dump the object with the specified function
     fn dump_object_function_here<T: ? Sized>(&mut self, value: &T, dump: DumpFunction<Self>);
     // Core function
@@ -125,6 +177,21 @@ pub trait Dumper {
         self.dump_padding(value);
         self.dump_object_here(value);
     }
+    // Dumps each element of slice
+    // (if T has no pointers they are all dumped as a single value, otherwise they are dumped individually)
+    #[inline]
+    fn dump_slice<T: Dump>(&mut self, slice: &[T]) {
+        if slice.is_empty() {
+            return;
+        }
+        if T::NO_POINTERS {
+            self.dump_value(slice);
+        } else {
+            for value in slice {
+                self.dump_object(value);
+            }
+        }
+    }
 
     fn dump_reference_here<T: ? Sized>(&mut self, value: &&T);
     #[inline]
@@ -133,6 +200,21 @@ pub trait Dumper {
         self.dump_reference_here(value);
     }
 
+    // Like dump_reference, but dumps a literal 0 if the pointer is null
+    #[inline]
+    fn dump_nullable_reference_here<T: ? Sized>(&mut self, value: &&T) {
+        if Address::new(*value) == Address::null() {
+            self.dump_value_here(&0usize);
+        } else {
+            self.dump_reference_here(value);
+        }
+    }
+    #[inline]
+    fn dump_nullable_reference<T: ? Sized>(&mut self, value: &&T) {
+        self.dump_padding(value);
+        self.dump_nullable_reference_here(value);
+    }
+
     fn reference_object_function_sized_position<T: ? Sized, P: ? Sized>(
         &mut self,
         value: &T,
@@ -202,7 +284,22 @@ pub trait Dumper {
     }
     #[inline]
     fn dump_reference_object_here<T: ? Sized + Dump>(&mut self, value: &&T) {
-        self.dump_reference_object_sized_here(value, mem::size_of_val(*value), mem::align_of_val(value))
+        self.dump_reference_object_sized_here(value, mem::size_of_val(*value), mem::align_of_val(*value))
+    }
+
+    // Like dump_reference_object, but dumps a literal 0 if the pointer is null
+    #[inline]
+    fn dump_nullable_reference_object_here<T: ? Sized + Dump>(&mut self, value: &&T) {
+        if Address::new(*value) == Address::null() {
+            self.dump_value_here(&0usize);
+        } else {
+            self.dump_reference_object_here(value);
+        }
+    }
+    #[inline]
+    fn dump_nullable_reference_object<T: ? Sized + Dump>(&mut self, value: &&T) {
+        self.dump_padding(value);
+        self.dump_nullable_reference_object_here(value);
     }
 
     #[inline]
@@ -322,6 +419,28 @@ impl<D: ? Sized + Dumper> DumpList<D> {
     pub fn add<T: ? Sized + Dump>(&mut self, value: &T) {
         self.add_position(value, value);
     }
+    // Adds value, to be dumped with the given function instead of T::dump
+    // (like T::dump, the function must start with a call to debug_record)
+    #[inline]
+    pub fn add_with<T: ? Sized>(&mut self, value: &T, dump: fn(&T, &mut D)) {
+        if mem::size_of_val(value) != 0 {
+            self.0.insert(
+                Address::new(value),
+                (Address::new(value), unsafe { mem::transmute::<fn(&T, &mut D), DumpFunction<D>>(dump) }),
+            );
+        }
+    }
+    // Adds value, to be dumped as a raw sequence of bytes
+    #[inline]
+    pub fn add_value<T>(&mut self, value: &T) {
+        self.add_with(value, dump_value_function::<T, D>);
+    }
+    // Adds value, to be dumped as padding
+    // (so that dump_with_gaps won't dump it's bytes)
+    #[inline]
+    pub fn add_padding<T>(&mut self, value: &T) {
+        self.add_with(value, dump_padding_function::<T, D>);
+    }
     #[inline]
     pub fn dump(&mut self, dumper: &mut D) {
         for (position, &(value, dump)) in &self.0 {
@@ -330,8 +449,31 @@ impl<D: ? Sized + Dumper> DumpList<D> {
         }
         self.0.clear();
     }
+    // Like dump, but dumps the bytes before and between the objects as raw values
+    // (e.g. because they contain an enum's discriminant)
+    #[inline]
+    pub fn dump_with_gaps(&mut self, dumper: &mut D) {
+        for (position, &(value, dump)) in &self.0 {
+            dumper.dump_prefix_value(position.to_ref::<()>());
+            dumper.dump_object_function_here(value.to_ref::<()>(), dump)
+        }
+        self.0.clear();
+    }
     #[inline]
     pub fn first(&self) -> &() {
         self.0.keys().next().unwrap().to_ref()
     }
 }
+
+unsafe fn drop_box<T>(value: *mut ()) {
+    drop(Box::from_raw(value as *mut T));
+}
+
+fn dump_value_function<T, D: ? Sized + Dumper>(value: &T, dumper: &mut D) {
+    dumper.debug_record::<()>("dump_value");
+    dumper.dump_value_here(value);
+}
+fn dump_padding_function<T, D: ? Sized + Dumper>(_: &T, dumper: &mut D) {
+    dumper.debug_record::<()>("dump_padding");
+    dumper.dump_padding_sized(mem::size_of::<T>());
+}
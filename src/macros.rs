@@ -15,6 +15,7 @@
 /*
 Documentation:
 (in order to use these you need a 'use rodal;' and 'use std;' statement)
+(with the "derive" feature, #[derive(Dump, Named)] can be used instead, see rodal-derive/src/lib.rs)
 
 Note: The folowing are used in the syntax given bellow for brevity
 GEN := ['[' args* ']']
@@ -23,15 +24,26 @@ SOURCE: ['=' source]
 	optional source type (defaulst to Self), the dump implementation will mem::transmute self to &source
 
 rodal_value!(GEN ty):
-	dumps ty as a raw sequence of bytes
+	dumps ty as a raw sequence of bytes (and sets Dump::NO_POINTERS, so arrays of ty are dumped in one go)
 
 rodal_object_referece!(GEN ty = &referant)
-	dumps ty as if it refers to a complete object of type referant
+	dumps ty as if it refers to a complete object of type referant (or is null)
 rodal_pointer!(GEN ty = *referant)
-	dumps ty as if it points to an instance of referant (which is not neccesarily a complete object)
+	dumps ty as if it points to an instance of referant (which is not neccesarily a complete object), or is null
 rodal_object!(GEN ty = source)
     dump ty in the same way as source
 
+rodal_replace!(GEN ty = '|' value '|' '{' replacement '}')
+	dumps the bytes of replacement (a value of type ty, which may use value: &ty) in place of the real value
+	(e.g. a freshly constructed lock, counter or cache), replacement must be position independent
+	(i.e. not contain any pointers), as it's bytes are dumped as a raw value
+	(this is rodal::dump_fresh_except with no data, which can be used directly to keep part of the real value,
+	e.g. the contents of a lock)
+rodal_replace!(GEN ty = '|' value '|' '->' target '{' replacement '}')
+	as above, but replacement is of type target (which must have the same size and alignment as ty),
+	and is dumped as an object (so it may contain pointers, e.g. a rodal::EmptyHashMap),
+	the dumper keeps replacement alive until it is dropped
+
 rodal_struct!(GEN ty '{' $(field),* '}' SOURCE)
 	dump self.fields... in the order specified.
 	(fields are expressions, so you can use '0' for a tuple struct)
@@ -40,31 +52,32 @@ rodal_unordered_struct!(GEN ty '{' $(field),* '}' SOURCE)
 	(this is less efficient than rodal_struct! as it builds a BTreeMap)
 
 rodal_enum!(GEN ty '{' $(variant),* '}' SOURCE)
-    Unlike the other macros, ty must be a single identifier.
+    Unlike the other macros, ty must be a path (e.g. Enum or module::Enum),
+    optionally followed by it's generic parameters (e.g. Result<T, E> or Token<'a, T>).
 
 	dumps an enum (will through unimplemented! for unspecified variants)
 	variant can either be:
 		unit
 		    the name of a unit variant
 		'(' tuple ':' $(element),+')'
-		    element can be any distinct set of identifiers (the elements will be dumped in the order given)
+		    element can be any distinct set of identifiers
 		'{' struct ':' $(field),+'}'
-		    field is the name of each field (they will be dumped in the order given)
+		    field is the name of each field
+	the elements/fields are dumped in memory order (as this may depend on the generic arguments),
+	and the bytes around them (which may contain the discriminant) are dumped as raw values
 rodal_unordered_enum!(GEN ty '{' $(variant),* '}' SOURCE)
-	Same as rodal_enum! but will dump tuple and struct elements in memory order, not the order given
+	Same as rodal_enum! (which now also dumps elements in memory order)
 
-rodal_array_impl!(len)
-	len should be a number, provides a generic implementation for all arrays
-	of the form [T; len], you only need to use this if len > 32
+Arrays of every length, and tuples of up to 32 elements, implement Dump
+(their elements are dumped in memory order)
 
-rodal_tuple_impl!($(n: T),+)
-	Each T should be a distinct identifier, and each n should be a number
-	Ths will provided a generic implementation for tuples, dumping the elements
-	in the order you specified 'n' in.
-	You only need to use this for tuples with more than 16 elements
+rodal_array_impl!(len) and rodal_tuple_impl!($(n: T),+)
+	deprecated, these no longer implement anything (see above),
+	they only check that the given array or tuple implements Dump
 
 */
 use std;
+use super::*;
 
 #[macro_export]
 #[cfg(debug_assertions)]
@@ -86,7 +99,7 @@ macro_rules! type_name {
 macro_rules! rodal_value {
     ([$($gen:tt)*] $ty:ty [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*]$ty {
         dumper.dump_value(fake_self);
-    } = $ty [$name]} ];
+    } = $ty [$name] const NO_POINTERS: bool = true;} ];
 
     ($ty:ty) => [ rodal_value!{[] $ty [stringify!($ty).to_string()]} ];
 }
@@ -96,7 +109,7 @@ macro_rules! rodal_object_reference {
     ([$($gen:tt)*] $ty:ty = &$referant:ty [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*]$ty {
         let reference = unsafe{std::mem::transmute::<&Self, &&($referant)>(fake_self)};
         if std::mem::size_of_val(*reference) > 0 {
-            dumper.dump_reference_object(reference);
+            dumper.dump_nullable_reference_object(reference);
         }
     } = $ty [$name]} ];
 
@@ -106,7 +119,7 @@ macro_rules! rodal_object_reference {
 #[macro_export]
 macro_rules! rodal_pointer {
     ([$($gen:tt)*] $ty:ty = *$referant:ty [$name:expr]) => [rodal___dump_impl!{(fake_self dumper D) [$($gen)*]$ty {
-        dumper.dump_reference(unsafe{std::mem::transmute::<&Self, &&($referant)>(fake_self)});
+        dumper.dump_nullable_reference(unsafe{std::mem::transmute::<&Self, &&$referant>(fake_self)});
     } = $ty [$name]} ];
 
     ($ty:ty = *$referant:ty) => [ rodal_pointer!{[] $ty = *$referant [stringify!($ty).to_string()]} ];
@@ -121,6 +134,40 @@ macro_rules! rodal_object {
     ($ty:ty = $source:ty) => [ rodal_object!{[] $ty = $source [stringify!($ty).to_string()]} ];
 }
 
+#[macro_export]
+macro_rules! rodal_replace {
+    ([$($gen:tt)*] $ty:ty = |$value:tt| -> $target:ty $replacement:block [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*]$ty {
+        let replacement: $target = {
+            let $value: &Self = fake_self;
+            $replacement
+        };
+        assert!(
+            std::mem::size_of::<$target>() == std::mem::size_of::<Self>() &&
+                std::mem::align_of::<$target>() == std::mem::align_of::<Self>(),
+            "the replacement for a {} has a different layout", <Self as $crate::Named>::name()
+        );
+        // The replacement may own objects that won't be dumped until the dump is finished
+        let replacement = dumper.keep_alive(replacement);
+        dumper.dump_object_here(unsafe { &*replacement });
+    } = $ty [$name]} ];
+    ([$($gen:tt)*] $ty:ty = |$value:tt| $replacement:block [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*]$ty {
+        let replacement: Self = {
+            let $value: &Self = fake_self;
+            $replacement
+        };
+        $crate::dump_fresh_except::<D, Self, (), Self>(
+            dumper,
+            fake_self,
+            None,
+            &replacement,
+            $crate::Address::new(&replacement) + std::mem::size_of::<Self>()
+        );
+    } = $ty [$name]} ];
+
+    ($ty:ty = |$value:tt| -> $target:ty $replacement:block) => [ rodal_replace!{[] $ty = |$value| -> $target $replacement [stringify!($ty).to_string()]} ];
+    ($ty:ty = |$value:tt| $replacement:block) => [ rodal_replace!{[] $ty = |$value| $replacement [stringify!($ty).to_string()]} ];
+}
+
 #[macro_export]
 macro_rules! rodal_struct {
     ([$($gen:tt)*] $ty:ty {$($field:tt),*} = $source:ty [$name:expr]) => [rodal___dump_impl!{(fake_self dumper D) [$($gen)*] $ty {
@@ -140,83 +187,84 @@ macro_rules! rodal_unordered_struct {
         list.dump(dumper);
     } = $source [$name]} ];
 
-    ([$($gen:tt)*] $ty:ty {$($field:tt),*}) => [ rodal_unordered_struct!{[$($gen)*]$ty {$($field),*} = $ty [$name]} ];
+    ([$($gen:tt)*] $ty:ty {$($field:tt),*} [$name:expr]) => [ rodal_unordered_struct!{[$($gen)*]$ty {$($field),*} = $ty [$name]} ];
     ($ty:ty {$($field:tt),*} = $source:ty) => [ rodal_unordered_struct!{[] $ty {$($field),*} = $source [stringify!($ty).to_string()]} ];
     ($ty:ty {$($field:tt),*}) => [ rodal_unordered_struct!{[] $ty {$($field),*} = $ty [stringify!($ty).to_string()]} ];
 }
 
 #[macro_export]
 macro_rules! rodal_enum {
-    ([$($gen:tt)*] $ty:ident {$($variant:tt),*} = $source:ty [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*] $ty {
-        use self::$ty::*;
+    ([$($gen:tt)*] $($path:ident)::+ $(<$($arg:tt),*>)? {$($variant:tt),*} = $source:ty [$name:expr]) => [
+        rodal___enum_impl!{[$($gen)*] ($($path)::+ $(<$($arg),*>)?) [$($path)::+] {$($variant),*} = $source [$name]}
+    ];
+    ([$($gen:tt)*] $($path:ident)::+ $(<$($arg:tt),*>)? {$($variant:tt),*} [$name:expr]) => [ rodal_enum!{[$($gen)*] $($path)::+ $(<$($arg),*>)? {$($variant),*} = $($path)::+ $(<$($arg),*>)? [$name]} ];
+    ([$($gen:tt)*] $($path:ident)::+ $(<$($arg:tt),*>)? {$($variant:tt),*}) => [ rodal_enum!{[$($gen)*] $($path)::+ $(<$($arg),*>)? {$($variant),*} = $($path)::+ $(<$($arg),*>)? [rodal___enum_name!([$($path)::+] $(<$($arg),*>)?)]} ];
+    ($($path:ident)::+ {$($variant:tt),*} = $source:ty) => [ rodal_enum!{[] $($path)::+ {$($variant),*} = $source [rodal___enum_name!([$($path)::+])]} ];
+    ($($path:ident)::+ {$($variant:tt),*}) => [ rodal_enum!{[] $($path)::+ {$($variant),*} = $($path)::+ [rodal___enum_name!([$($path)::+])]} ];
+}
+// The enum's path is passed as a single token tree, so that it can be used in the repetition over the variants
+#[macro_export]
+macro_rules! rodal___enum_impl {
+    ([$($gen:tt)*] ($($ty:tt)*) $path:tt {$($variant:tt),*} = $source:ty [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*] $($ty)* {
         match fake_self {
-            $(rodal___variant_pattern!($variant) => {rodal___variant_impl!{(fake_self dumper D) $variant}})*
+            $(rodal___variant_pattern!($path $variant) => {rodal___variant_impl!{(fake_self dumper D) $variant}})*
             _ => unimplemented!()
         }
     } = $source [$name]} ];
-    ([$($gen:tt)*] $ty:ident {$($variant:tt),*}) => [ rodal_enum!{[$($gen)*]$ty {$($variant),*} = $ty [$name]} ];
-    ($ty:ident {$($variant:tt),*} = $source:ty) => [ rodal_enum!{[] $ty {$($variant),*} = $source [stringify!($ty).to_string()]} ];
-    ($ty:ident {$($variant:tt),*}) => [ rodal_enum!{[] $ty {$($variant),*} = $ty [stringify!($ty).to_string()]} ];
 }
+// rodal_enum! already dumps fields in memory order
 #[macro_export]
 macro_rules! rodal_unordered_enum {
-    ([$($gen:tt)*] $ty:ident {$($variant:tt),*} = $source:ty [$name:expr]) => [ rodal___dump_impl!{(fake_self dumper D) [$($gen)*] $ty {
-        use self::$ty::*;
-        match fake_self {
-            $(rodal___variant_pattern!($variant) => {rodal___unordered_variant_impl!{(fake_self dumper D) $variant}})*
-            _ => unimplemented!()
+    ($($args:tt)*) => [ rodal_enum!{$($args)*} ];
+}
+
+// The name of an enum (as written), followed by the names of it's generic type arguments (lifetimes are omitted)
+#[macro_export]
+macro_rules! rodal___enum_name {
+    ([$($path:ident)::+]) => [ [$(stringify!($path)),+].join("::") ];
+    ([$($path:ident)::+] <$($arg:tt),*>) => [{
+        let args: std::vec::Vec<std::string::String> =
+            std::vec![$(rodal___generic_arg_name!($arg)),*].into_iter().flatten().collect();
+        if args.is_empty() {
+            rodal___enum_name!([$($path)::+])
+        } else {
+            format!("{}<{}>", rodal___enum_name!([$($path)::+]), args.join(", "))
         }
-    } = $source [$name]} ];
-    ([$($gen:tt)*] $ty:ident {$($variant:tt),*}) => [ rodal_unordered_enum!{[$($gen)*]$ty {$($variant),*} = $ty [$name]} ];
-    ($ty:ident {$($variant:tt),*} = $source:ty) => [ rodal_unordered_enum!{[] $ty {$($variant),*} = $source [stringify!($ty).to_string()]} ];
-    ($ty:ident {$($variant:tt),*}) => [ rodal_unordered_enum!{[] $ty {$($variant),*} = $ty [stringify!($ty).to_string()]} ];
+    }];
+}
+#[macro_export]
+macro_rules! rodal___generic_arg_name {
+    ($lifetime:lifetime) => [ None ];
+    ($param:ident) => [ Some($crate::type_name::<$param>()) ];
 }
 
 #[macro_export]
 macro_rules! rodal___variant_pattern {
-    ({$variant:ident : $field0:ident $(,$field:ident)*}) => [
-        &$variant{ref $field0, $(ref $field,)*}
+    ([$($path:tt)*] {$variant:ident : $field0:ident $(,$field:ident)*}) => [
+        &$($path)*::$variant{ref $field0, $(ref $field,)*}
     ];
-    (($variant:ident : $field0:ident $(,$field:ident)*)) => [
-        &$variant(ref $field0, $(ref $field,)*)
+    ([$($path:tt)*] ($variant:ident : $field0:ident $(,$field:ident)*)) => [
+        &$($path)*::$variant(ref $field0, $(ref $field,)*)
     ];
-    ($variant:ident) => [
-        &$variant
+    ([$($path:tt)*] $variant:ident) => [
+        &$($path)*::$variant
     ];
 }
+// The fields of a variant are dumped in memory order (which may depend on the generic arguments),
+// and the bytes before, between and after them (which may contain the discriminant,
+// and can differ between variants) are dumped as raw values
 #[macro_export]
 macro_rules! rodal___variant_impl {
-    (($fake_self:ident $dumper:ident $D:ident) {$variant:ident : $field0:ident $(,$field:ident)*}) => [
-        $dumper.dump_prefix_value($field0);
-        $dumper.dump_object($field0);
-        $($dumper.dump_object($field);)*
-        $dumper.dump_suffix_value($fake_self);
-    ];
-    (($fake_self:ident $dumper:ident $D:ident) ($variant:ident : $field0:ident $(,$field:ident)*)) => [
-        $dumper.dump_prefix_value($field0);
-        $dumper.dump_object($field0);
-        $($dumper.dump_object($field);)*
-        $dumper.dump_suffix_value($fake_self);
-    ];
-    (($fake_self:ident $dumper:ident $D:ident) $variant:ident) => [
-        $dumper.dump_value($fake_self)
-    ];
-}
-
-#[macro_export]
-macro_rules! rodal___unordered_variant_impl {
-    (($fake_self:ident $dumper:ident $D:ident) {$variant:ident : $field0:ident $(,$field:ident)*}) => [
+    (($fake_self:ident $dumper:ident $D:ident) {$variant:ident : $($field:ident),+}) => [
         let mut list = $crate::DumpList::<$D>::new();
-        $(list.add($field);)*
-        $dumper.dump_prefix_value(list.first());
-        list.dump($dumper);
+        $(list.add($field);)+
+        list.dump_with_gaps($dumper);
         $dumper.dump_suffix_value($fake_self);
     ];
-    (($fake_self:ident $dumper:ident $D:ident) ($variant:ident : $field0:ident $(,$field:ident)*)) => [
+    (($fake_self:ident $dumper:ident $D:ident) ($variant:ident : $($field:ident),+)) => [
         let mut list = $crate::DumpList::<$D>::new();
-        $(list.add($field);)*
-        $dumper.dump_prefix_value(list.first());
-        list.dump($dumper);
+        $(list.add($field);)+
+        list.dump_with_gaps($dumper);
         $dumper.dump_suffix_value($fake_self);
     ];
     (($fake_self:ident $dumper:ident $D:ident) $variant:ident) => [
@@ -226,7 +274,7 @@ macro_rules! rodal___unordered_variant_impl {
 
 #[macro_export]
 macro_rules! rodal___dump_impl {
-    (($fake_self:ident $dumper:ident $D:ident) [$($gen:tt)*] $ty:ty $body:block = $source:tt [$name:expr]) => [
+    (($fake_self:ident $dumper:ident $D:ident) [$($gen:tt)*] $ty:ty $body:block = $source:tt [$name:expr] $($item:item)*) => [
         #[allow(unreachable_patterns)]
         #[allow(unused_variables)]
         #[allow(unused_imports)]
@@ -236,6 +284,7 @@ macro_rules! rodal___dump_impl {
                 let $fake_self: &($source) = unsafe{std::mem::transmute(self)};
                 $body
             }
+            $($item)*
         }
         impl <$($gen)*> $crate::Named for $ty {
             fn name()->std::string::String{
@@ -263,52 +312,73 @@ macro_rules! rodal_named {
     ];
 }
 
+rodal___dump_impl!{(fake_self dumper D) [T: Dump, const N: usize] [T; N] {
+    dumper.dump_slice(fake_self);
+} = [T; N] [format!("[{}; {}]", type_name::<T>(), N)] const NO_POINTERS: bool = T::NO_POINTERS;}
+
+// Tuples may be reordered, so their elements are dumped in memory order
+macro_rules! rodal___tuple_impl {
+    ($($n:tt : $ty:ident,)*) => [ rodal___dump_impl!{(fake_self dumper D) [$($ty: $crate::Dump),*] ($($ty,)*) {
+        let mut list = $crate::DumpList::<D>::new();
+        $(list.add(&fake_self.$n);)*
+        list.dump(dumper);
+    } = ($($ty,)*) [{
+        let names: &[String] = &[$($crate::type_name::<$ty>()),*];
+        if names.len() == 1 {
+            format!("({},)", names[0])
+        } else {
+            format!("({})", names.join(", "))
+        }
+    }]
+    // (if the tuple has no padding, as that would otherwise be dumped as zeros)
+    const NO_POINTERS: bool = true $(&& $ty::NO_POINTERS)* &&
+        0 $(+ std::mem::size_of::<$ty>())* == std::mem::size_of::<($($ty,)*)>();
+    }];
+}
+
+// Implements Dump for the tuples of each prefix of the given elements
+macro_rules! rodal___tuple_impls {
+    ([$($done:tt)*]) => { rodal___tuple_impl!{$($done)*} };
+    ([$($done:tt)*] $n:tt : $ty:ident $(, $rest_n:tt : $rest_ty:ident)*) => {
+        rodal___tuple_impl!{$($done)*}
+        rodal___tuple_impls!{[$($done)* $n : $ty,] $($rest_n : $rest_ty),*}
+    };
+}
+rodal___tuple_impls! {[]
+    0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7,
+    8: T8, 9: T9, 10: T10, 11: T11, 12: T12, 13: T13, 14: T14, 15: T15,
+    16: T16, 17: T17, 18: T18, 19: T19, 20: T20, 21: T21, 22: T22, 23: T23,
+    24: T24, 25: T25, 26: T26, 27: T27, 28: T28, 29: T29, 30: T30, 31: T31
+}
+
+// These used to implement Dump for arrays and tuples that the built in impls didn't cover,
+// now that every array (and tuple of up to 32 elements) implements Dump, they only check that the given one does
 #[macro_export]
+#[deprecated(note = "arrays of every length, and tuples of up to 32 elements, already implement Dump")]
 macro_rules! rodal_array_impl {
-    ($len: tt) => {
-        rodal___dump_impl!{(fake_self dumper D) [T: $crate::Dump] [T; $len] {
-            for i in 0..$len {
-                dumper.dump_object(&fake_self[i]);
+    ($len:tt) => {
+        const _: () = {
+            #[allow(dead_code)]
+            fn assert_dump<T: $crate::Dump>() {}
+            #[allow(dead_code)]
+            fn check<T: $crate::Dump>() {
+                assert_dump::<[T; $len]>();
             }
-        } = [T; $len] [format!("[{}, {}]", $crate::type_name::<T>(), $len)]}
+        };
     };
 }
 
 #[macro_export]
+#[deprecated(note = "arrays of every length, and tuples of up to 32 elements, already implement Dump")]
 macro_rules! rodal_tuple_impl {
-    ($($n:tt : $ty:ident),*) => [ rodal___dump_impl!{(fake_self dumper D) [$($ty: $crate::Dump),*] ($($ty,)*) {
-        $(dumper.dump_object(&fake_self.$n);)*
-    } = ($($ty,)*) [format!("{:?}", ($($crate::type_name::<$ty>()),*))]}];
-}
-
-macro_rules! rodal___array_impls {
-    ($($len:tt)+) => { $(rodal_array_impl!{$len})+ }
-}
-rodal___array_impls!(01 02 03 04 05 06 07 08 09 10 11 12 13 14 15 16
-                     17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 63 64);
-
-macro_rules! rodal___tuple_impls {
-    ($(($( $n:tt: $ty:ident ),*))+) => { $(rodal_tuple_impl!{$($n : $ty),*})+ }
-}
-rodal___tuple_impls! {
-    ()
-    (0: T0)
-    (0: T0, 1: T1)
-    (0: T0, 1: T1, 2: T2)
-    (0: T0, 1: T1, 2: T2, 3: T3)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9, 10: T10)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9, 10: T10, 11: T11)
-    // TODO: Can't format (typeids)
-/*
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9, 10: T10, 11: T11, 12: T12)
-(0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9, 10: T10, 11: T11, 12: T12, 13: T13)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9, 10: T10, 11: T11, 12: T12, 13: T13, 14: T14)
-    (0: T0, 1: T1, 2: T2, 3: T3, 4: T4, 5: T5, 6: T6, 7: T7, 8: T8, 9: T9, 10: T10, 11: T11, 12: T12, 13: T13, 14: T14, 15: T15)
-*/
+    ($($n:tt : $ty:ident),*) => {
+        const _: () = {
+            #[allow(dead_code)]
+            fn assert_dump<T: $crate::Dump>() {}
+            #[allow(dead_code)]
+            fn check<$($ty: $crate::Dump),*>() {
+                assert_dump::<($($ty,)*)>();
+            }
+        };
+    };
 }
@@ -0,0 +1,273 @@
+// Copyright 2017 The Australian National University
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Write protection of loaded dumps (for catching code that modifies supposedly immutable data)
+// When a write protected dump is written to, the SIGSEGV handler reports the address
+// (and the object it is in, if the dump has an object table) and then lets the program crash
+
+extern crate libc;
+
+use std::io;
+use std::mem;
+use std::ptr;
+use std::sync::Once;
+use std::sync::atomic::{fence, Ordering};
+use super::*;
+
+// The layout of the RODAL_OBJECTS table written by the AsmDumper
+#[repr(C)]
+pub(crate) struct ObjectTable {
+    len: usize,
+    entries: [ObjectEntry; 0],
+}
+#[repr(C)]
+pub(crate) struct ObjectEntry {
+    address: *const u8,
+    size: usize,
+    type_name: *const u8,
+    type_name_len: usize,
+}
+
+#[derive(Clone, Copy)]
+struct ProtectedRegion {
+    start: Address,
+    end: Address,
+    // Null if the dump has no object table
+    objects: *const ObjectTable,
+}
+
+// One for the main program, and one for each image
+const MAX_PROTECTED_REGIONS: usize = MAX_RODAL_IMAGES + 1;
+static mut PROTECTED_REGIONS: [Option<ProtectedRegion>; MAX_PROTECTED_REGIONS] = [None; MAX_PROTECTED_REGIONS];
+static PROTECTED_REGIONS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+static INSTALL_HANDLER: Once = Once::new();
+// The handlers that were installed before ours (we pass on any faults that aren't in a protected region)
+static mut PREVIOUS_SEGV_ACTION: Option<libc::sigaction> = None;
+static mut PREVIOUS_BUS_ACTION: Option<libc::sigaction> = None;
+
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+// The whole pages in [start, end) (or None if there aren't any), as only they are protected
+// (the AsmDumper page aligns the dump, so normally this is all of it)
+fn protected_pages(start: Address, end: Address) -> Option<(usize, usize)> {
+    let page_size = page_size();
+    let page_start = (start.value() + page_size - 1) & !(page_size - 1);
+    let page_end = end.value() & !(page_size - 1);
+    if page_start < page_end {
+        Some((page_start, page_end))
+    } else {
+        None
+    }
+}
+
+/// Makes the whole pages in [start, end) read only, and reports any writes to them
+/// (objects is the dump's RODAL_OBJECTS table, or null if it dosn't have one)
+pub(crate) unsafe fn protect_region(
+    start: Address,
+    end: Address,
+    objects: *const ObjectTable,
+) -> Result<(), LoadError> {
+    let (page_start, page_end) = match protected_pages(start, end) {
+        Some(pages) => pages,
+        None => return Ok(()) // Nothing to protect
+    };
+
+    INSTALL_HANDLER.call_once(|| install_handler());
+
+    {
+        let _guard = PROTECTED_REGIONS_LOCK.lock().unwrap();
+        let regions = &mut *ptr::addr_of_mut!(PROTECTED_REGIONS);
+        match regions.iter().position(|region| region.is_none()) {
+            Some(slot) => {
+                regions[slot] = Some(ProtectedRegion {
+                    start,
+                    end,
+                    objects,
+                })
+            }
+            None => return Err(LoadError::TooManyImages)
+        }
+        // Make sure the handler can see the region before anything can fault in it
+        fence(Ordering::SeqCst);
+    }
+
+    if libc::mprotect(page_start as *mut libc::c_void, page_end - page_start, libc::PROT_READ) != 0 {
+        unprotect_region(start);
+        Err(LoadError::ProtectFailed(io::Error::last_os_error().to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+// Makes the region starting at 'start' writable again, and stops reporting faults in it
+// (this does nothing if the region was never protected)
+pub(crate) unsafe fn unprotect_region(start: Address) {
+    let mut unprotected = None;
+    {
+        let _guard = PROTECTED_REGIONS_LOCK.lock().unwrap();
+        for region in (*ptr::addr_of_mut!(PROTECTED_REGIONS)).iter_mut() {
+            if region.is_some_and(|region| region.start == start) {
+                unprotected = region.take();
+            }
+        }
+        fence(Ordering::SeqCst);
+    }
+
+    if let Some((page_start, page_end)) = unprotected.and_then(|region| protected_pages(region.start, region.end)) {
+        // The pages were writable when they were loaded (the dumper puts the dump in a writable section),
+        // if this fails the pages are still mapped read only, so there's nothing better we can do
+        libc::mprotect(
+            page_start as *mut libc::c_void,
+            page_end - page_start,
+            libc::PROT_READ | libc::PROT_WRITE
+        );
+    }
+}
+
+unsafe fn install_handler() {
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = handle_fault as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) as usize;
+    action.sa_flags = libc::SA_SIGINFO;
+    libc::sigemptyset(&mut action.sa_mask);
+
+    let mut previous: libc::sigaction = mem::zeroed();
+    assert!(libc::sigaction(libc::SIGSEGV, &action, &mut previous) == 0);
+    PREVIOUS_SEGV_ACTION = Some(previous);
+    // Some systems (e.g. macos) report writes to read only pages as a SIGBUS
+    assert!(libc::sigaction(libc::SIGBUS, &action, &mut previous) == 0);
+    PREVIOUS_BUS_ACTION = Some(previous);
+}
+
+#[cfg(not(target_os = "macos"))]
+unsafe fn fault_address(info: *mut libc::siginfo_t) -> Address {
+    Address::from_ptr((*info).si_addr())
+}
+#[cfg(target_os = "macos")]
+unsafe fn fault_address(info: *mut libc::siginfo_t) -> Address {
+    Address::from_ptr((*info).si_addr)
+}
+
+// Everything called from here has to be async signal safe (in particular we can't allocate)
+extern "C" fn handle_fault(signal: libc::c_int, info: *mut libc::siginfo_t, context: *mut libc::c_void) {
+    unsafe {
+        let address = fault_address(info);
+        let regions = &*ptr::addr_of!(PROTECTED_REGIONS);
+        let region = regions
+            .iter()
+            .filter_map(|&region| region)
+            .find(|region| region.start <= address && address < region.end);
+
+        match region {
+            Some(region) => {
+                write_stderr(b"rodal: write to write protected dump at ");
+                write_hex(address.value());
+                if let Some(object) = find_object(region.objects, address) {
+                    write_stderr(b" (offset ");
+                    write_hex((address - Address::from_ptr(object.address)) as usize);
+                    write_stderr(b" in object ");
+                    write_hex(Address::from_ptr(object.address).value());
+                    write_stderr(b" of type ");
+                    write_stderr(std::slice::from_raw_parts(object.type_name, object.type_name_len));
+                    write_stderr(b")");
+                }
+                write_stderr(b"\n");
+
+                // Crash when the write is retried
+                let mut action: libc::sigaction = mem::zeroed();
+                action.sa_sigaction = libc::SIG_DFL;
+                libc::sigaction(signal, &action, ptr::null_mut());
+            }
+            None => {
+                // Not our fault, pass it on to the handler that was installed before ours
+                // (our handler stays installed, so later writes to a protected dump are still reported)
+                let previous = if signal == libc::SIGSEGV {
+                    &*ptr::addr_of!(PREVIOUS_SEGV_ACTION)
+                } else {
+                    &*ptr::addr_of!(PREVIOUS_BUS_ACTION)
+                };
+                match *previous {
+                    Some(ref previous) => chain_fault(signal, info, context, previous),
+                    None => raise_default(signal)
+                }
+            }
+        }
+    }
+}
+
+// Calls the handler 'previous' as if it had recieved the signal instead of us
+unsafe fn chain_fault(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    context: *mut libc::c_void,
+    previous: &libc::sigaction,
+) {
+    if previous.sa_flags & libc::SA_SIGINFO != 0 {
+        let handler = mem::transmute::<usize, extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)>(
+            previous.sa_sigaction
+        );
+        handler(signal, info, context);
+    } else if previous.sa_sigaction == libc::SIG_DFL {
+        raise_default(signal);
+    } else if previous.sa_sigaction != libc::SIG_IGN {
+        let handler = mem::transmute::<usize, extern "C" fn(libc::c_int)>(previous.sa_sigaction);
+        handler(signal);
+    }
+}
+
+// Restores the default action for 'signal', and raises it again
+// (it's blocked until we return, and a real fault will also recur when the instruction is retried)
+unsafe fn raise_default(signal: libc::c_int) {
+    let mut action: libc::sigaction = mem::zeroed();
+    action.sa_sigaction = libc::SIG_DFL;
+    libc::sigaction(signal, &action, ptr::null_mut());
+    libc::raise(signal);
+}
+
+// Finds the object in the table that contains address
+unsafe fn find_object<'a>(objects: *const ObjectTable, address: Address) -> Option<&'a ObjectEntry> {
+    if objects.is_null() {
+        return None;
+    }
+    let entries = std::slice::from_raw_parts((*objects).entries.as_ptr(), (*objects).len);
+    // The entries are sorted by address, so find the last one that starts at or before address
+    let index = match entries.binary_search_by(|entry| Address::from_ptr(entry.address).cmp(&address)) {
+        Ok(index) => index,
+        Err(0) => return None,
+        Err(index) => index - 1
+    };
+    let entry = &entries[index];
+    if address < Address::from_ptr(entry.address) + entry.size {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+fn write_stderr(message: &[u8]) {
+    unsafe { libc::write(libc::STDERR_FILENO, message.as_ptr() as *const libc::c_void, message.len()) };
+}
+
+fn write_hex(value: usize) {
+    let mut buffer = [0u8; 2 + 2 * mem::size_of::<usize>()];
+    buffer[0] = b'0';
+    buffer[1] = b'x';
+    for i in 0..2 * mem::size_of::<usize>() {
+        let digit = (value >> (4 * (2 * mem::size_of::<usize>() - 1 - i))) & 0xf;
+        buffer[2 + i] = b"0123456789abcdef"[digit];
+    }
+    write_stderr(&buffer);
+}
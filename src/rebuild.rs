@@ -0,0 +1,77 @@
+// Copyright 2017 The Australian National University
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Rebuilding objects whose layout is private (and so can't be dumped in place) when a dump is loaded
+// Their dump function writes a stub in their place and calls Dumper::record_fixup_here,
+// once loaded, apply_asm_fixups (or Image::apply_fixups) replaces each stub with a real object
+
+use std::collections::HashMap;
+use super::*;
+
+/// A type that can be rebuilt from the stub its dump function wrote in its place
+/// (the stub is identified by the type's Named::name)
+///
+/// # Safety
+/// rebuild must understand the stub written by the type's dump function (for the same type arguments)
+pub unsafe trait Rebuild: Sized + Named {
+    /// Replace the stub at 'slot' with a real Self (this takes ownership of anything the stub owns)
+    ///
+    /// # Safety
+    /// slot must point to a stub written by Self's dump function, that hasn't already been rebuilt
+    unsafe fn rebuild(slot: *mut Self);
+}
+
+unsafe fn rebuild_erased<T: Rebuild>(slot: *mut ()) {
+    T::rebuild(slot as *mut T)
+}
+
+/// The set of types that can be rebuilt when loading a dump
+/// Every type with a fixup in the dump must be registered (with exactly the same type arguments it was dumped with)
+#[derive(Debug)]
+pub struct Rebuilders(HashMap<String, unsafe fn(*mut ())>);
+
+impl Rebuilders {
+    /// Creates a set of rebuilders containing only the type of the tags written by AsmDumper::dump_tags
+    pub fn new() -> Rebuilders {
+        let mut rebuilders = Rebuilders(HashMap::new());
+        rebuilders.register::<HashMap<usize, Vec<*const ()>>>();
+        rebuilders
+    }
+    pub fn register<T: Rebuild>(&mut self) -> &mut Self {
+        self.0.insert(T::name(), rebuild_erased::<T>);
+        self
+    }
+    pub(crate) fn get(&self, type_name: &str) -> Option<unsafe fn(*mut ())> {
+        self.0.get(type_name).copied()
+    }
+}
+
+impl Default for Rebuilders {
+    fn default() -> Rebuilders {
+        Rebuilders::new()
+    }
+}
+
+// The layout of the RODAL_FIXUPS table written by the AsmDumper
+#[repr(C)]
+pub(crate) struct FixupTable {
+    pub len: usize,
+    pub entries: [FixupEntry; 0],
+}
+#[repr(C)]
+pub(crate) struct FixupEntry {
+    pub slot: *mut (),
+    pub type_name: *const u8,
+    pub type_name_len: usize,
+}
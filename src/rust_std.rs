@@ -22,6 +22,7 @@ rodal_pointer!(['a, T: Named] &'a mut T = *T [type_name!("&mut {}", T)]);
 rodal_pointer!([T: Named] *const T = *T [type_name!("*const {}", T)]);
 rodal_pointer!([T: Named] * mut T = *T [type_name!("*mut {}", T)]);
 rodal_pointer!([T: Named] std::sync::atomic::AtomicPtr<T> = *T [type_name!("std::sync::atomic::AtomicPtr<{}>", T)]);
+rodal_pointer!([T: Named] std::ptr::NonNull<T> = *T [type_name!("std::ptr::NonNull<{}>", T)]);
 
 rodal_object_reference!([T: Dump] std::boxed::Box<T> = &T [type_name!("std::boxed::Box<{}>", T)]);
 rodal_object!([T: Dump] std::boxed::Box<[T]> = Repr<T> [type_name!("std::boxed::Box<[{}]>", T)]);
@@ -48,22 +49,26 @@ rodal_value!(f32);
 rodal_value!(f64);
 rodal_value!(char);
 
-//rodal_enum!([T: Dump] std::option::Option<T>{None, (Some: val)});
-// This is implemented manually as the rodal_enum! macro dosn't work with generics...
+// When Option<T> is the same size as T (e.g. Option<&T>, Option<Box<T>> and Option<NonNull<T>>),
+// None is stored in a niche of T (e.g. as a null pointer), so Some(val) is dumped exactly like val is,
+// and None as a raw value (so a null pointer is written as 0)
 rodal_named!([T: Named] std::option::Option<T> [type_name!("std::option::Option<{}>", T)]);
 unsafe impl<T: Dump> Dump for std::option::Option<T> {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
-        match self {
-            &Some(ref val) => {
+        match *self {
+            Some(ref val) if std::mem::size_of::<T>() == std::mem::size_of::<Self>() => dumper.dump_object(val),
+            Some(ref val) if std::mem::size_of::<T>() != 0 => {
                 dumper.dump_prefix_value(val);
-                dumper.dump_object(val);
+                dumper.dump_object_here(val);
                 dumper.dump_suffix_value(self);
             }
-            &None => dumper.dump_value(self)
+            // There's nothing to dump other than the discriminant
+            _ => dumper.dump_value(self)
         }
     }
 }
+rodal_enum!([T: Dump, E: Dump] Result<T, E>{(Ok: val), (Err: err)} [type_name!("std::result::Result<{}, {}>", T, E)]);
 
 // The types declared here have been copied (and slightly modified) from the rust source code
 // This is neccesary so we can use private fields, and types, that are unstable, by making copies whith identical layouts.
@@ -111,62 +116,126 @@ rodal_object!([T: Dump] Shared<[T]> = Repr<T> [type_name!("core::ptr::Shared<[{}
 rodal_object!(Shared<str> = Repr<u8>);
 
 // public collections::vec (libcollections/vec.rs)
-pub struct Vec<T> {
-    buf: RawVec<T>,
-    pub len: usize
-}
-// unstable alloc::raw_vec (liballoc/rawvec.rs)
-/* NOTE: in rust v1.21 the definition was change to:
-pub struct RawVec<T, A: Alloc = Heap> {
-    ptr: Unique<T>,
-    cap: usize,
-    a: A,
-}
-However, it is currently only used be Vec (which dosn't provide an ovveride for 'A'), and 'Heap' is an empty struct
-*/
-struct RawVec<T> {
-    pub ptr: Unique<T>,
-    pub cap: usize
-}
+// Vec's fields (and their order) are private, but it is guaranteed to consist of a pointer, a capacity and a length,
+// so we dump it a word at a time, and use as_ptr() to find which word is the pointer
 rodal_named!([T: Named] std::vec::Vec<T> [type_name!("std::vec::Vec<{}>", T)]);
 unsafe impl<T: Dump> Dump for std::vec::Vec<T> {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
+        assert!(std::mem::size_of::<Self>() == 3 * std::mem::size_of::<usize>());
+        let words: &[usize; 3] = unsafe { std::mem::transmute(self) };
+        let buffer_size = std::mem::size_of::<T>() * self.capacity();
+        debug_assert!(buffer_size == 0 || words.iter().filter(|&&word| word == self.as_ptr() as usize).count() == 1);
 
-        // Transmute to the fake_std version so we can access private fields
-        let fake_self: &Vec<T> = unsafe { std::mem::transmute(self) };
-
-        if std::mem::size_of::<T>() * fake_self.buf.cap == 0 {
-            // Dosn't point to any real memory, so just dump a raw value
-            dumper.dump_value(&fake_self.buf.ptr);
-        } else {
-            dumper.dump_reference_object_function_sized_position(
-                self, // the argument to pass to the dump function
-                // The function to use to dump the contents
-                unsafe { std::mem::transmute::<fn(&Vec<T>, &mut D), DumpFunction<D>>(Vec::<T>::dump_contents) },
-                fake_self.buf.ptr.as_ref(), // Where to actually dump the data
-                std::mem::size_of::<T>() * fake_self.buf.cap,
-                std::mem::align_of::<T>()
-            );
+        for word in words {
+            if buffer_size != 0 && *word == self.as_ptr() as usize {
+                dumper.dump_reference_object_function_sized_position(
+                    self, // the argument to pass to the dump function
+                    // The function to use to dump the contents
+                    unsafe { std::mem::transmute::<fn(&std::vec::Vec<T>, &mut D), DumpFunction<D>>(dump_vec_contents) },
+                    unsafe { std::mem::transmute::<&usize, &&T>(word) }, // Where to actually dump the data
+                    buffer_size,
+                    std::mem::align_of::<T>()
+                );
+            } else {
+                // The capacity or length (or the pointer, if it dosn't point to any real memory)
+                dumper.dump_value(word);
+            }
         }
-
-        // Dump the fields of the vector
-        dumper.dump_object(&fake_self.buf.cap);
-        dumper.dump_object(&fake_self.len);
     }
 }
 
-rodal_named!([T: Named] Vec<T> [type_name!("std::vec::Vec<{}>", T)]);
-impl<T: Dump> Vec<T> {
-    fn dump_contents<D: ?Sized + Dumper>(&self, dumper: &mut D) {
-        dumper.debug_record::<Self>("dump_contents");
-        let real_self: &std::vec::Vec<T> = unsafe { mem::transmute(self) };
-        dumper.set_position(Address::from_ptr(self.buf.ptr.as_ptr()));
-        for val in real_self {
-            dumper.dump_object(val);
+// This is transmuted to a DumpFunction for the Vec, so it has to take a &Vec (not a slice)
+#[allow(clippy::ptr_arg)]
+fn dump_vec_contents<T: Dump, D: ?Sized + Dumper>(vec: &std::vec::Vec<T>, dumper: &mut D) {
+    dumper.debug_record::<std::vec::Vec<T>>("dump_contents");
+    dumper.set_position(Address::from_ptr(vec.as_ptr()));
+    dumper.dump_slice(vec);
+}
+// public std::collections::vec_deque (src/liballoc/collections/vec_deque/mod.rs)
+// Like Vec, VecDeque's fields are private, but it consists of a pointer, a capacity, a length and the index of
+// it's first element (in some order), so we dump it a word at a time, and use as_slices() to find the pointer
+// The live elements are in one or two segments of the buffer (the second starts at the start of the buffer)
+rodal_named!([T: Named] std::collections::VecDeque<T> [type_name!("std::collections::VecDeque<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::collections::VecDeque<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let words: &[usize; 4] = unsafe { std::mem::transmute(self) };
+        let buffer_size = std::mem::size_of::<T>() * self.capacity();
+        let buffer = if buffer_size == 0 { None } else { Some(vec_deque_buffer(self)) };
+
+        for (i, word) in words.iter().enumerate() {
+            if Some(i) == buffer {
+                dumper.dump_reference_object_function_sized_position(
+                    self,
+                    unsafe {
+                        std::mem::transmute::<fn(&std::collections::VecDeque<T>, &mut D), DumpFunction<D>>(
+                            dump_vec_deque_contents
+                        )
+                    },
+                    unsafe { std::mem::transmute::<&usize, &&T>(word) },
+                    buffer_size,
+                    std::mem::align_of::<T>()
+                );
+            } else {
+                // The capacity, length or head (or the pointer, if it dosn't point to any real memory)
+                dumper.dump_value(word);
+            }
         }
     }
 }
+
+// Returns the index of the word of 'deque' that points to it's buffer (which must have a non zero size)
+fn vec_deque_buffer<T>(deque: &std::collections::VecDeque<T>) -> usize {
+    assert!(std::mem::size_of::<std::collections::VecDeque<T>>() == 4 * std::mem::size_of::<usize>());
+    let words: &[usize; 4] = unsafe { std::mem::transmute(deque) };
+    let (first, second) = deque.as_slices();
+    let first = first.as_ptr() as usize;
+    let size = std::mem::size_of::<T>();
+
+    // The buffer is the word such that the first segment starts 'head' elements after it
+    // (where head is another word), and the second segment (if any) starts at it
+    let candidates: Vec<usize> = (0..4)
+        .filter(|&i| {
+            let buffer = words[i];
+            if buffer > first || !(first - buffer).is_multiple_of(size) ||
+                (!second.is_empty() && second.as_ptr() as usize != buffer)
+            {
+                return false;
+            }
+            let head = (first - buffer) / size;
+            head < deque.capacity() && (0..4).any(|j| j != i && words[j] == head)
+        })
+        .collect();
+    assert!(candidates.len() == 1, "the layout of std::collections::VecDeque has changed");
+    candidates[0]
+}
+
+fn dump_vec_deque_contents<T: Dump, D: ?Sized + Dumper>(deque: &std::collections::VecDeque<T>, dumper: &mut D) {
+    dumper.debug_record::<std::collections::VecDeque<T>>("dump_contents");
+    let words: &[usize; 4] = unsafe { std::mem::transmute(deque) };
+    dumper.set_position(Address::from_ptr(words[vec_deque_buffer(deque)] as *const T));
+    // Dump the live elements in memory order (the slots between them are uninitialised, and so are dumped as padding)
+    let (first, second) = deque.as_slices();
+    dumper.dump_slice(second);
+    dumper.dump_slice(first);
+}
+
+// public std::collections::binary_heap (src/liballoc/collections/binary_heap.rs)
+// A BinaryHeap is just a Vec (whose elements are in heap order)
+rodal_named!([T: Named] std::collections::BinaryHeap<T> [type_name!("std::collections::BinaryHeap<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::collections::BinaryHeap<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let data: &std::vec::Vec<T> = unsafe { std::mem::transmute(self) };
+        assert!(
+            data.as_ptr() == self.as_slice().as_ptr() && data.len() == self.len() && data.capacity() == self.capacity(),
+            "the layout of std::collections::BinaryHeap has changed"
+        );
+        dumper.dump_object(data);
+    }
+}
+
 // public collections::string (src/libcollections/string.rs)
 pub struct String {
     pub vec: std::vec::Vec<u8>
@@ -177,160 +246,404 @@ rodal_struct!(std::string::String { vec } = String);
 pub struct Arc<T: ?Sized> {
     ptr: Shared<ArcInner<T>>
 }
-rodal_struct!([T: ?Sized + Dump] std::sync::Arc<T>{ptr} = Arc<T> [type_name!("std::sync::Arc<{}>", T)]);
+rodal_named!([T: ?Sized + Named] std::sync::Arc<T> [type_name!("std::sync::Arc<{}>", T)]);
+unsafe impl<T: ?Sized + Dump> Dump for std::sync::Arc<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let fake_self: &Arc<T> = unsafe { std::mem::transmute(self) };
+        dumper.count_reference(Address::from_ptr(fake_self.ptr.pointer.0), false);
+        dumper.dump_object(&fake_self.ptr);
+    }
+}
 
 // private alloc::arc (liballoc/arc.rs)
+#[repr(C)] // The real ArcInner is also repr(C)
 pub struct ArcInner<T: ?Sized> {
     pub strong: std::sync::atomic::AtomicUsize,
     pub weak: std::sync::atomic::AtomicUsize,
     pub data: T
 }
-rodal_struct!([T: ?Sized + Dump] ArcInner<T>{strong, weak, data} [type_name!("alloc::arc<{}>", T)]);
-
-// private std::sys::poision (libstd/syscommon/poison.rs)
-struct Flag {
-    pub failed: std::sync::atomic::AtomicBool
+rodal_named!([T: ?Sized + Named] ArcInner<T> [type_name!("alloc::arc<{}>", T)]);
+unsafe impl<T: ?Sized + Dump> Dump for ArcInner<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let strong = self.strong.load(std::sync::atomic::Ordering::SeqCst);
+        dumper.dump_reference_count(&self.strong, Address::new(self), false, strong);
+        dumper.dump_reference_count(&self.weak, Address::new(self), true, self.weak.load(std::sync::atomic::Ordering::SeqCst));
+        // If only Weak's are left, data has already been dropped
+        if strong != 0 {
+            dumper.dump_object(&self.data);
+        }
+    }
 }
-rodal_value!(Flag);
 
-// public core::cell (libcore/cell.rs)
-pub struct UnsafeCell<T: ?Sized> {
-    pub value: T
+// public alloc::arc (liballoc/arc.rs)
+rodal_named!([T: Named] std::sync::Weak<T> [type_name!("std::sync::Weak<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::sync::Weak<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let inner: &&ArcInner<T> = unsafe { std::mem::transmute(self) };
+        if is_dangling(*inner) {
+            dumper.dump_value(self);
+        } else {
+            dumper.count_reference(Address::new(*inner), true);
+            dumper.dump_reference_object(inner);
+        }
+    }
 }
-rodal_struct!([T: ?Sized + Dump] UnsafeCell<T>{value} [type_name!("std::cell::UnsafeCell<{}>", T)]);
 
-// public std::sync (libstd/sync/rwlock.rs)
-pub struct RwLock<T: ?Sized> {
-    pub inner: Box<self::sys::RWLock>, // sys::RWLock (system dependent struct, the exact value can't be dumped be we can create a new one and dump that instead)
-    poison: Flag,
-    pub data: UnsafeCell<T>
+// Whether a Weak was made by Weak::new (and so dosn't point to anything)
+fn is_dangling<T: ?Sized>(inner: &T) -> bool {
+    Address::new(inner).value() == usize::MAX
 }
 
-// Acquires a read lock on it's contents before it dumps
-rodal_named!([T: ?Sized + Named] std::sync::RwLock<T> [type_name!("std::sync::RwLock<{}>", T)]);
-unsafe impl<T: ?Sized + Dump> Dump for std::sync::RwLock<T> {
+// public alloc::rc (liballoc/rc.rs)
+pub struct Rc<T: ?Sized> {
+    ptr: Shared<RcBox<T>>
+}
+rodal_named!([T: Named] std::rc::Rc<T> [type_name!("std::rc::Rc<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::rc::Rc<T> {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
-        use std::ops::Deref;
-        // Acquire a read lock to self (just so no one tries to modify the contents whilst we try and dump it)
-        let lock = self.read().unwrap();
-        let data: &T = lock.deref();
-        let fake_self: &RwLock<T> = unsafe { std::mem::transmute(self) };
-
-        dumper.dump_object(&fake_self.inner);
-        dumper.dump_object(&fake_self.poison);
-        dumper.dump_object(data);
+        let fake_self: &Rc<T> = unsafe { std::mem::transmute(self) };
+        dumper.count_reference(Address::from_ptr(fake_self.ptr.pointer.0), false);
+        dumper.dump_object(&fake_self.ptr);
     }
 }
 
-// std::sync (src/libstd/sync/mutex.rs)
-pub struct Mutex<T: ?Sized> {
-    inner: Box<sys::Mutex>,
-    poison: Flag,
-    pub data: UnsafeCell<T>
+// private alloc::rc (liballoc/rc.rs)
+#[repr(C)] // The real RcBox is also repr(C)
+pub struct RcBox<T: ?Sized> {
+    pub strong: std::cell::Cell<usize>,
+    pub weak: std::cell::Cell<usize>,
+    pub value: T
 }
-
-rodal_named!([T: ?Sized + Named] std::sync::Mutex<T> [type_name!("std::sync::Mutex<{}>", T)]);
-unsafe impl<T: ?Sized + Dump> Dump for std::sync::Mutex<T> {
+rodal_named!([T: ?Sized + Named] RcBox<T> [type_name!("alloc::rc::RcBox<{}>", T)]);
+unsafe impl<T: ?Sized + Dump> Dump for RcBox<T> {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
-        use std::ops::Deref;
-        // Acquire a lock to self (just so no one tries to modify the contents whilst we try and dump it)
+        dumper.dump_reference_count(&self.strong, Address::new(self), false, self.strong.get());
+        dumper.dump_reference_count(&self.weak, Address::new(self), true, self.weak.get());
+        // If only Weak's are left, value has already been dropped
+        if self.strong.get() != 0 {
+            dumper.dump_object(&self.value);
+        }
+    }
+}
 
-        let lock = self.lock().unwrap();
-        let data: &T = lock.deref();
-        let fake_self: &Mutex<T> = unsafe { std::mem::transmute(self) };
+// The pointer of an Rc<[T]> points to the start of an RcBox<[T]> (and it's length is the number of elements)
+rodal_named!([T: Named] std::rc::Rc<[T]> [type_name!("std::rc::Rc<[{}]>", T)]);
+unsafe impl<T: Dump> Dump for std::rc::Rc<[T]> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let fake_self: &Rc<[T]> = unsafe { std::mem::transmute(self) };
+        let rc_box: &RcBox<[T]> = unsafe { &*fake_self.ptr.pointer.0 };
+        let repr: &Repr<T> = unsafe { std::mem::transmute(self) };
 
-        dumper.dump_object(&fake_self.inner);
-        dumper.dump_object(&fake_self.poison);
-        dumper.dump_object(data);
+        dumper.count_reference(Address::new(rc_box), false);
+        dumper.dump_reference_object_function_sized_position(
+            self, // the argument to pass to the dump function
+            unsafe { std::mem::transmute::<fn(&std::rc::Rc<[T]>, &mut D), DumpFunction<D>>(dump_rc_slice_box) },
+            unsafe { std::mem::transmute::<&std::rc::Rc<[T]>, &&RcBox<[T]>>(self) }, // Where the RcBox is
+            std::mem::size_of_val(rc_box),
+            std::mem::align_of_val(rc_box)
+        );
+        dumper.dump_object(&repr.len);
     }
 }
 
-// private std (libstd/sys/)
-#[cfg(windows)]
-mod sys {
-    use libc;
-    // std::sys::rwlock (libstd/sys/windows/rwlock.rs)
-    pub struct RWLock {
-        pub inner: super::UnsafeCell<SRWLOCK>
+fn dump_rc_slice_box<T: Dump, D: ?Sized + Dumper>(rc: &std::rc::Rc<[T]>, dumper: &mut D) {
+    dumper.debug_record::<std::rc::Rc<[T]>>("dump_box");
+    let fake_rc: &Rc<[T]> = unsafe { std::mem::transmute(rc) };
+    let rc_box: &RcBox<[T]> = unsafe { &*fake_rc.ptr.pointer.0 };
+
+    dumper.set_position(Address::new(rc_box));
+    dumper.dump_reference_count(&rc_box.strong, Address::new(rc_box), false, rc_box.strong.get());
+    dumper.dump_reference_count(&rc_box.weak, Address::new(rc_box), true, rc_box.weak.get());
+    dumper.dump_slice(&rc_box.value);
+}
+
+// An Rc<str> is just an Rc<[u8]>
+rodal_named!(std::rc::Rc<str>);
+unsafe impl Dump for std::rc::Rc<str> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        dumper.dump_object(unsafe { std::mem::transmute::<&Self, &std::rc::Rc<[u8]>>(self) });
     }
+}
 
-    // std::sys::c (libstd/sys/windows/c.rs)
-    #[repr(C)]
-    pub struct SRWLOCK {
-        pub ptr: LPVOID
+// public alloc::rc (liballoc/rc.rs)
+rodal_named!([T: Named] std::rc::Weak<T> [type_name!("std::rc::Weak<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::rc::Weak<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let rc_box: &&RcBox<T> = unsafe { std::mem::transmute(self) };
+        if is_dangling(*rc_box) {
+            dumper.dump_value(self);
+        } else {
+            dumper.count_reference(Address::new(*rc_box), true);
+            dumper.dump_reference_object(rc_box);
+        }
     }
-    pub type LPVOID = *mut libc::c_void;
+}
 
-    pub struct Mutex {
-        lock: AtomicUsize,
-        held: UnsafeCell<bool>
+// The value of a (public) std::cell::UnsafeCell is accessible through get()
+rodal_named!([T: ?Sized + Named] std::cell::UnsafeCell<T> [type_name!("std::cell::UnsafeCell<{}>", T)]);
+unsafe impl<T: ?Sized + Dump> Dump for std::cell::UnsafeCell<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        dumper.dump_object(unsafe { &*self.get() });
     }
 }
-#[cfg(target_os = "redox")]
-mod sys {
-    use libc;
-    use std;
-    // std::sys::rwlock (libstd/sys/redox/rwlock.rs)
-    pub struct RWLock {
-        pub mutex: Mutex
+
+// public core::cell (libcore/cell.rs)
+rodal_named!([T: ?Sized + Named] std::cell::Cell<T> [type_name!("std::cell::Cell<{}>", T)]);
+unsafe impl<T: ?Sized + Dump> Dump for std::cell::Cell<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        dumper.dump_object(unsafe { &*self.as_ptr() });
     }
-    // std::sys::mutex (libstd/sys/redox/mutex.rs)
-    pub struct Mutex {
-        pub lock: super::UnsafeCell<i32>
+}
+
+// public core::cell::once (libcore/cell/once.rs)
+// A OnceCell<T> is just an UnsafeCell<Option<T>>
+rodal_named!([T: Named] std::cell::OnceCell<T> [type_name!("std::cell::OnceCell<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::cell::OnceCell<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let inner: &Option<T> = unsafe { std::mem::transmute(self) };
+        assert!(
+            inner.as_ref().map(|value| value as *const T) == self.get().map(|value| value as *const T),
+            "the layout of std::cell::OnceCell has changed"
+        );
+        dumper.dump_object(inner);
     }
 }
-#[cfg(unix)]
-mod sys {
-    use libc;
-    use std;
-    // std::sys::rwlock (libstd/sys/unix/rwlock.rs)
-    pub struct RWLock {
-        pub inner: super::UnsafeCell<libc::pthread_rwlock_t>,
-        pub write_locked: super::UnsafeCell<bool>,
-        pub num_readers: std::sync::atomic::AtomicUsize
+
+/// Dumps 'real', a value containing 'data', but instead of the bytes of real (other than data),
+/// dumps those of 'fresh', a newly constructed value with the same layout
+/// ('fresh_data' is the position of data in 'fresh', it's contents are never read)
+/// If data is None (i.e. real has no initialised data), padding is dumped in it's place
+/// This is used for locks and cells, as their state is private, but is initialy position independent,
+/// and by rodal_replace! (with no data, to dump the whole of fresh)
+/// (T must be Sized, as a fresh value can only be made by constructing one around a MaybeUninit<T>)
+pub fn dump_fresh_except<D: ?Sized + Dumper, R, T: Dump, F>(
+    dumper: &mut D,
+    real: &R,
+    data: Option<&T>,
+    fresh: &F,
+    fresh_data: Address,
+) {
+    let offset = fresh_data - Address::new(fresh);
+    assert!(
+        std::mem::size_of::<R>() == std::mem::size_of::<F>() &&
+            data.is_none_or(|data| offset == Address::new(data) - Address::new(real)),
+        "layout of {} is inconsistent with {}",
+        std::any::type_name::<R>(),
+        std::any::type_name::<F>()
+    );
+    let offset = offset as usize;
+    let end = offset + std::mem::size_of::<T>();
+
+    dumper.dump_value_sized_here(fresh, offset);
+    if let Some(data) = data {
+        dumper.dump_object(data);
     }
-    pub struct Mutex {
-        pub inner: super::UnsafeCell<libc::pthread_mutex_t>
+    // data's dump may not have written all of it's bytes (e.g. if it's a stub)
+    dumper.dump_padding((Address::new(real) + end).to_ref::<()>());
+    dumper.dump_value_sized_here((Address::new(fresh) + end).to_ref::<u8>(), std::mem::size_of::<R>() - end);
+}
+
+// public std::sync (libstd/sync/rwlock.rs)
+// Acquires a read lock on it's contents before it dumps
+// (the lock itself is dumped in it's initial state)
+rodal_named!([T: Named] std::sync::RwLock<T> [type_name!("std::sync::RwLock<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::sync::RwLock<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        use std::ops::Deref;
+        // Acquire a read lock to self (just so no one tries to modify the contents whilst we try and dump it),
+        // waiting for a write lock could deadlock (e.g. if this thread holds it), and a poisoned lock is dumped
+        // unpoisoned (as the fresh lock is)
+        let lock = match self.try_read() {
+            Ok(lock) => lock,
+            Err(std::sync::TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => {
+                panic!("can't dump a {} whilst it is write locked", Self::name())
+            }
+        };
+        let data: &T = lock.deref();
+
+        // get_mut dosn't lock, so 'fresh' will remain in it's initial state
+        let mut fresh = std::sync::RwLock::new(std::mem::MaybeUninit::<T>::uninit());
+        let fresh_data = Address::new(fresh.get_mut().unwrap());
+        dump_fresh_except(dumper, self, Some(data), &fresh, fresh_data);
     }
 }
 
-rodal_named!(sys::RWLock);
-unsafe impl Dump for sys::RWLock {
+// std::sync (src/libstd/sync/mutex.rs)
+rodal_named!([T: Named] std::sync::Mutex<T> [type_name!("std::sync::Mutex<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::sync::Mutex<T> {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
+        use std::ops::Deref;
+        // Acquire a lock to self (just so no one tries to modify the contents whilst we try and dump it),
+        // as with RwLock we don't wait for it, and a poisoned lock is dumped unpoisoned
+        let lock = match self.try_lock() {
+            Ok(lock) => lock,
+            Err(std::sync::TryLockError::Poisoned(err)) => err.into_inner(),
+            Err(std::sync::TryLockError::WouldBlock) => panic!("can't dump a {} whilst it is locked", Self::name())
+        };
+        let data: &T = lock.deref();
+
+        let mut fresh = std::sync::Mutex::new(std::mem::MaybeUninit::<T>::uninit());
+        let fresh_data = Address::new(fresh.get_mut().unwrap());
+        dump_fresh_except(dumper, self, Some(data), &fresh, fresh_data);
+    }
+}
 
-        // Create a new std::sync::RwLock, and dump its value of inner
-        // (so that when we load the dump the RwLock will have it's initial state)
-        let lock: RwLock<()> = unsafe { std::mem::transmute(std::sync::RwLock::<()>::new(())) };
-        dumper.dump_value_here(lock.inner.as_ref());
+// public std::sync (libstd/sync/condvar.rs)
+// Dumped in it's initial state (as no thread can be waiting on it when the dump is loaded)
+rodal_replace!(std::sync::Condvar = |_| { std::sync::Condvar::new() });
+
+// public core::cell (libcore/cell.rs)
+// The borrow flag is dumped in it's initial (unborrowed) state
+// If it's mutably borrowed, it's contents may be being modified, so an error is recorded and they aren't dumped
+rodal_named!([T: Named] std::cell::RefCell<T> [type_name!("std::cell::RefCell<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::cell::RefCell<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let mut fresh = std::cell::RefCell::new(std::mem::MaybeUninit::<T>::uninit());
+        let fresh_data = Address::new(fresh.get_mut());
+        // Keep it borrowed, so no one can modify the contents whilst we dump it
+        match self.try_borrow() {
+            Ok(borrow) => dump_fresh_except(dumper, self, Some(&*borrow), &fresh, fresh_data),
+            Err(_) => {
+                dumper.record_error(format!("a {} was mutably borrowed, so its contents weren't dumped", Self::name()));
+                dump_fresh_except::<D, Self, T, _>(dumper, self, None, &fresh, fresh_data);
+            }
+        }
     }
 }
 
-rodal_named!(sys::Mutex);
-unsafe impl Dump for sys::Mutex {
+// public std::sync::once_lock (src/libstd/sync/once_lock.rs)
+// The Once is dumped in it's initial state, or it's completed state if the OnceLock has been initialised
+rodal_named!([T: Named] std::sync::OnceLock<T> [type_name!("std::sync::OnceLock<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::sync::OnceLock<T> {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
+        let initialised = std::sync::OnceLock::new();
+        let _ = initialised.set(std::mem::MaybeUninit::<T>::uninit());
+        let data_offset = Address::new(initialised.get().unwrap()) - Address::new(&initialised);
 
-        // Create a new std::sync::RwLock, and dump its value of inner
-        // (so that when we load the dump the RwLock will have it's initial state)
-        let mutex: Mutex<()> = unsafe { std::mem::transmute(std::sync::Mutex::<()>::new(())) };
-        dumper.dump_value_here(&*mutex.inner);
+        match self.get() {
+            Some(data) => dump_fresh_except(dumper, self, Some(data), &initialised, Address::new(&initialised) + data_offset),
+            None => {
+                let fresh = std::sync::OnceLock::<std::mem::MaybeUninit<T>>::new();
+                dump_fresh_except::<D, Self, T, _>(dumper, self, None, &fresh, Address::new(&fresh) + data_offset);
+            }
+        }
     }
 }
+
 // public std::collections::hash_map (src/libstd/collections/hash/map.rs)
-pub struct RandomState {
-    pub k0: u64,
-    pub k1: u64
-}
-rodal_struct!(std::collections::hash_map::RandomState { k0, k1 } = RandomState);
-pub struct DefaultResizePolicy;
-rodal_struct!(DefaultResizePolicy {});
-pub struct HashMap<K, V, S = std::collections::hash_map::RandomState> {
+// RandomState is just a pair of keys
+rodal_value!(std::collections::hash_map::RandomState);
+// As is DefaultHasher (a SipHasher seeded by a RandomState)
+rodal_value!(std::collections::hash_map::DefaultHasher);
+// BuildHasherDefault is empty, H is usually from another crate (e.g. FxHasher),
+// and so can't implement Named, so we use it's rust type name instead
+rodal_value!([H] std::hash::BuildHasherDefault<H> [format!("std::hash::BuildHasherDefault<{}>", std::any::type_name::<H>())]);
+
+// HashMap's and HashSet's tables are private (and depend on the hashes of their elements),
+// so we dump a HashStub in their place, and rebuild them when the dump is loaded (see Rebuild)
+// The stubs elements are dumped as an array of complete objects, which is given the position of
+// the lowest addressed element (so that it won't overlap any other complete object),
+// as this moves the elements, dumping a reference into a HashMap or HashSet panics
+#[repr(C)]
+pub struct HashStub<E, S> {
+    pub elements: *const E,
+    pub len: usize,
     pub hash_builder: S,
-    pub table: RawTable<K, V>,
-    pub resize_policy: DefaultResizePolicy
+}
+
+impl<E, S> HashStub<E, S> {
+    unsafe fn elements<'a>(&self) -> &'a [E] {
+        if self.len == 0 || std::mem::size_of::<E>() == 0 {
+            std::slice::from_raw_parts(std::ptr::NonNull::dangling().as_ptr(), self.len)
+        } else {
+            std::slice::from_raw_parts(self.elements, self.len)
+        }
+    }
+}
+
+// Dumps a HashStub in place of 'collection'
+fn dump_hash_stub<D: ?Sized + Dumper, C: Named, E, S: Dump>(
+    dumper: &mut D,
+    collection: &C,
+    hash_builder: &S,
+    len: usize,
+    (lowest_element, highest_element): (Address, Address),
+    dump_elements: DumpFunction<D>,
+) {
+    assert!(std::mem::size_of::<HashStub<E, S>>() <= std::mem::size_of::<C>());
+    assert!(std::mem::align_of::<HashStub<E, S>>() <= std::mem::align_of::<C>());
+    let start = Address::new(collection);
+    let hash_builder_offset = {
+        let stub = std::mem::MaybeUninit::<HashStub<E, S>>::uninit();
+        let hash_builder = unsafe { std::ptr::addr_of!((*stub.as_ptr()).hash_builder) };
+        Address::from_ptr(hash_builder) - Address::from_ptr(stub.as_ptr())
+    };
+
+    dumper.record_fixup_here(C::name());
+    let size = std::mem::size_of::<E>() * len;
+    if size == 0 {
+        // There is no memory to point to
+        dumper.dump_value_here(&0usize);
+    } else {
+        // This must be before the elements are referenced, which would resolve any earlier references to them
+        dumper.forbid_references(lowest_element, highest_element + std::mem::size_of::<E>(), C::name());
+        dumper.dump_reference_object_function_sized_position_here(
+            collection,
+            dump_elements,
+            &lowest_element.to_ref::<E>(),
+            size,
+            std::mem::align_of::<E>()
+        );
+    }
+    dumper.dump_value_here(&len);
+    dumper.dump_padding((start + hash_builder_offset).to_ref::<()>());
+    dumper.dump_object_here(hash_builder);
+}
+
+// The lowest and highest of 'elements' (or nulls if there are none)
+fn element_bounds<I: Iterator<Item = Address>>(elements: I) -> (Address, Address) {
+    elements
+        .fold(None, |bounds, element| match bounds {
+            Some((lowest, highest)) => Some((std::cmp::min(lowest, element), std::cmp::max(highest, element))),
+            None => Some((element, element))
+        })
+        .unwrap_or((Address::null(), Address::null()))
+}
+
+// Dumps 'value' at 'offset' bytes from 'start' (the position of the first array element),
+// padding from wherever the previous value's dump actually ended
+// (which may be before the end of the value, e.g. if it was a stub, or had trailing padding)
+fn dump_relative<D: ?Sized + Dumper, T: Dump>(dumper: &mut D, start: Address, offset: usize, value: &T) {
+    dumper.dump_padding((start + offset).to_ref::<()>());
+    dumper.dump_object_here(value);
+}
+
+// The offsets of the fields of (K, V)
+fn pair_offsets<K, V>() -> (usize, usize) {
+    let pair = std::mem::MaybeUninit::<(K, V)>::uninit();
+    let start = Address::from_ptr(pair.as_ptr());
+    unsafe {
+        (
+            (Address::from_ptr(std::ptr::addr_of!((*pair.as_ptr()).0)) - start) as usize,
+            (Address::from_ptr(std::ptr::addr_of!((*pair.as_ptr()).1)) - start) as usize
+        )
+    }
 }
 
 // The Eq + Hash and BuildHasher contstratins are needed
@@ -343,109 +656,302 @@ unsafe impl<K: Eq + std::hash::Hash + Dump, V: Dump, S: std::hash::BuildHasher +
 {
     fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
         dumper.debug_record::<Self>("dump");
-        // Transmute to the fake_std version so we can access private fields
-        let fake_self: &HashMap<K, V, S> = unsafe { std::mem::transmute(self) };
-
-        dumper.dump_object(&fake_self.hash_builder);
+        let (key_offset, _) = pair_offsets::<K, V>();
+        let bounds = element_bounds(self.keys().map(|key| Address::new(key) - key_offset));
+        dump_hash_stub::<D, Self, (K, V), S>(
+            dumper,
+            self,
+            self.hasher(),
+            self.len(),
+            bounds,
+            unsafe {
+                std::mem::transmute::<fn(&std::collections::HashMap<K, V, S>, &mut D), DumpFunction<D>>(
+                    dump_hash_map_elements
+                )
+            }
+        );
+    }
+}
 
-        // Dump table
-        dumper.dump_object(&fake_self.table.capacity_mask);
-        dumper.dump_object(&fake_self.table.size);
-        if fake_self.table.capacity() == 0 {
-            // Not an actual pointer (there is no associated memory)
-            dumper.dump_value(&fake_self.table.hashes);
+fn dump_hash_map_elements<K: Eq + std::hash::Hash + Dump, V: Dump, S: std::hash::BuildHasher + Dump, D: ?Sized + Dumper>(
+    map: &std::collections::HashMap<K, V, S>,
+    dumper: &mut D,
+) {
+    dumper.debug_record::<std::collections::HashMap<K, V, S>>("dump_elements");
+    let (key_offset, value_offset) = pair_offsets::<K, V>();
+    let size = std::mem::size_of::<(K, V)>();
+    let start = dumper.current_position();
+    for (i, (key, value)) in map.iter().enumerate() {
+        // Dump the fields in memory order
+        if key_offset <= value_offset {
+            dump_relative(dumper, start, i * size + key_offset, key);
+            dump_relative(dumper, start, i * size + value_offset, value);
         } else {
-            // Compute the size and alignment of the associated memory area
-            // (this was adapted from the real std's RawTable's Drop function)
-            let hashes_size = fake_self.table.capacity() * std::mem::size_of::<HashUint>();
-            let hash_align = std::mem::align_of::<HashUint>();
-            let pairs_size = fake_self.table.capacity() * std::mem::size_of::<(K, V)>();
-            let pair_align = std::mem::align_of::<(K, V)>();
-
-            // Rounds up hash_size to be a multiple of pairs_align (this works as pairs_align is a power of 2)
-            let pairs_offset = (hashes_size + pair_align - 1) & !(pair_align - 1);
-
-            let size = pairs_offset + pairs_size;
-            let align = std::cmp::max(hash_align, pair_align);
-
-            let pos = super::Address::new(unsafe { &*fake_self.table.hashes.ptr() });
-            dumper.dump_padding(&fake_self.table.hashes);
-            dumper.dump_reference_object_function_sized_position_offset_here(
-                fake_self, // the argument to pass to the dump function
-                // The function to use to dump the contents
-                unsafe {
-                    std::mem::transmute::<fn(&HashMap<K, V, S>, &mut D), DumpFunction<D>>(
-                        HashMap::<K, V, S>::dump_contents
-                    )
-                },
-                &pos.to_ref::<HashUint>(), // Where to actually dump the data
-                size,
-                align,
-                fake_self.table.hashes.tag() as isize
-            );
+            dump_relative(dumper, start, i * size + value_offset, value);
+            dump_relative(dumper, start, i * size + key_offset, key);
         }
-        dumper.dump_object(&fake_self.resize_policy);
     }
 }
-rodal_named!([K: Eq + std::hash::Hash + Named, V: Named, S: std::hash::BuildHasher + Named] HashMap<K, V, S> [type_name!("std::collections::HashMap<{}, {}, {}>", K, V, S)]);
-impl<K: Eq + std::hash::Hash + Dump, V: Dump, S: std::hash::BuildHasher + Dump> HashMap<K, V, S> {
-    fn dump_contents<D: ?Sized + Dumper>(&self, dumper: &mut D) {
-        dumper.debug_record::<Self>("dump_contents");
-        let real_pos = unsafe { &*self.table.hashes.ptr() };
-        dumper.set_position(Address::new(real_pos));
-        // Dump the stored hashes
-        dumper.dump_value_sized(real_pos, self.table.capacity() * std::mem::size_of::<HashUint>());
-
-        let real_self: &std::collections::HashMap<K, V, S> = unsafe { std::mem::transmute(self) };
-
-        // WARNING: We're assuming here that iteration happens in memory order
-        // (so far it has worked, but if it dosn,t try using the comment out code bellow instead)
-        for (key, value) in real_self {
-            dumper.dump_object(key); // Assuming eveything is stored in this order
-            dumper.dump_object(value);
+
+unsafe impl<K: Eq + std::hash::Hash + Named, V: Named, S: std::hash::BuildHasher + Named> Rebuild
+    for std::collections::HashMap<K, V, S>
+{
+    unsafe fn rebuild(slot: *mut Self) {
+        let stub = std::ptr::read(slot as *const HashStub<(K, V), S>);
+        let mut map = std::collections::HashMap::with_capacity_and_hasher(stub.len, std::ptr::read(&stub.hash_builder));
+        for element in stub.elements() {
+            let (key, value) = std::ptr::read(element);
+            map.insert(key, value);
         }
+        std::mem::forget(stub);
+        std::ptr::write(slot, map);
     }
 }
 
-// private std::collections::hash_map (src/libstd/collections/hash/table.rs)
-pub struct TaggedHashUintPtr(Unique<HashUint>);
+// public std::collections::hash_set (src/libstd/collections/hash/set.rs)
+rodal_named!([T: Eq + std::hash::Hash + Named, S: std::hash::BuildHasher + Named] std::collections::HashSet<T, S> [type_name!("std::collections::HashSet<{}, {}>", T, S)]);
+unsafe impl<T: Eq + std::hash::Hash + Dump, S: std::hash::BuildHasher + Dump> Dump for std::collections::HashSet<T, S> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let bounds = element_bounds(self.iter().map(|element| Address::new(element)));
+        dump_hash_stub::<D, Self, T, S>(
+            dumper,
+            self,
+            self.hasher(),
+            self.len(),
+            bounds,
+            unsafe {
+                std::mem::transmute::<fn(&std::collections::HashSet<T, S>, &mut D), DumpFunction<D>>(
+                    dump_hash_set_elements
+                )
+            }
+        );
+    }
+}
 
-// Note: this is a tagged pointer, but it will either have the value
-// of the underlying pointer or the pointer + 1
-// Either way the dumper will preserve the tag,
-// and it will point within the tables memory, so the Dumper will store it properly
-rodal_pointer!(TaggedHashUintPtr = *HashUint);
-impl TaggedHashUintPtr {
-    #[inline]
-    fn tag(&self) -> bool {
-        (self.0.as_ptr() as usize) & 1 == 1
+fn dump_hash_set_elements<T: Eq + std::hash::Hash + Dump, S: std::hash::BuildHasher + Dump, D: ?Sized + Dumper>(
+    set: &std::collections::HashSet<T, S>,
+    dumper: &mut D,
+) {
+    dumper.debug_record::<std::collections::HashSet<T, S>>("dump_elements");
+    let start = dumper.current_position();
+    for (i, element) in set.iter().enumerate() {
+        dump_relative(dumper, start, i * std::mem::size_of::<T>(), element);
     }
-    #[inline]
-    fn ptr(&self) -> *mut HashUint {
-        (self.0.as_ptr() as usize & !1) as *mut HashUint
+}
+
+unsafe impl<T: Eq + std::hash::Hash + Named, S: std::hash::BuildHasher + Named> Rebuild
+    for std::collections::HashSet<T, S>
+{
+    unsafe fn rebuild(slot: *mut Self) {
+        let stub = std::ptr::read(slot as *const HashStub<T, S>);
+        let mut set = std::collections::HashSet::with_capacity_and_hasher(stub.len, std::ptr::read(&stub.hash_builder));
+        for element in stub.elements() {
+            set.insert(std::ptr::read(element));
+        }
+        std::mem::forget(stub);
+        std::ptr::write(slot, set);
     }
 }
-// private std::collections::hash_map (src/libstd/collections/hash/table.rs)
-pub type HashUint = usize;
 
-// private std::collections::hash_map (src/libstd/collections/hash/table.rs)
-pub struct RawTable<K, V> {
-    pub capacity_mask: usize,
-    pub size: usize,
-    pub hashes: TaggedHashUintPtr,
-    pub marker: std::marker::PhantomData<(K, V)>
+// public std::collections::btree_map (src/liballoc/collections/btree/map.rs)
+// Unlike HashMap, a BTreeMap's layout only depends on the order of it's keys, so we can dump it's node tree in place
+pub struct BTreeMap<K, V> {
+    root: Option<NodeRef<K, V>>,
+    length: usize,
+    _marker: std::marker::PhantomData<Box<(K, V)>>
+}
+
+// private std::collections::btree::node (src/liballoc/collections/btree/node.rs)
+const BTREE_B: usize = 6;
+const BTREE_CAPACITY: usize = 2 * BTREE_B - 1;
+
+// The owned root of a tree
+struct NodeRef<K, V> {
+    height: usize,
+    node: std::ptr::NonNull<LeafNode<K, V>>,
+    _marker: std::marker::PhantomData<()>
+}
+
+struct LeafNode<K, V> {
+    parent: Option<std::ptr::NonNull<InternalNode<K, V>>>,
+    parent_idx: std::mem::MaybeUninit<u16>, // Only initialised if parent is
+    len: u16,
+    keys: [std::mem::MaybeUninit<K>; BTREE_CAPACITY], // Only the first len are initialised
+    vals: [std::mem::MaybeUninit<V>; BTREE_CAPACITY]
 }
-impl<K, V> RawTable<K, V> {
-    pub fn capacity(&self) -> usize {
-        self.capacity_mask.wrapping_add(1)
+rodal_named!([K: Named, V: Named] LeafNode<K, V> [type_name!("alloc::collections::btree::node::LeafNode<{}, {}>", K, V)]);
+
+#[repr(C)] // The real InternalNode is also repr(C)
+struct InternalNode<K, V> {
+    data: LeafNode<K, V>,
+    edges: [std::mem::MaybeUninit<std::ptr::NonNull<LeafNode<K, V>>>; 2 * BTREE_B] // Only the first len + 1 are initialised
+}
+rodal_named!([K: Named, V: Named] InternalNode<K, V> [type_name!("alloc::collections::btree::node::InternalNode<{}, {}>", K, V)]);
+
+// Returns every node of 'map' (and it's height), found by walking the tree through our copies of it's types
+// Panics if this dosn't find exactly the entries map.iter() does (i.e. if our copies are wrong)
+fn btree_nodes<K, V>(map: &std::collections::BTreeMap<K, V>) -> Vec<(*const LeafNode<K, V>, usize)> {
+    let fake_map: &BTreeMap<K, V> = unsafe { std::mem::transmute(map) };
+    let mut nodes = Vec::new();
+    let mut entries = Vec::new();
+    if let Some(ref root) = fake_map.root {
+        unsafe { walk_btree(root.node.as_ptr(), root.height, None, &mut nodes, &mut entries) };
+    }
+    let expected: Vec<(*const K, *const V)> = map.iter().map(|(key, val)| (key as *const K, val as *const V)).collect();
+    assert!(
+        fake_map.length == map.len() && entries == expected,
+        "the layout of std::collections::BTreeMap has changed"
+    );
+    nodes
+}
+
+// Visits the node and it's descendants, adding their entries (in order) to 'entries'
+unsafe fn walk_btree<K, V>(
+    node: *const LeafNode<K, V>,
+    height: usize,
+    parent: Option<(*const LeafNode<K, V>, u16)>,
+    nodes: &mut Vec<(*const LeafNode<K, V>, usize)>,
+    entries: &mut Vec<(*const K, *const V)>,
+) {
+    let leaf = &*node;
+    let len = leaf.len as usize;
+    let parent_matches = match parent {
+        Some((parent, parent_idx)) => {
+            leaf.parent.map(|real_parent| real_parent.as_ptr() as *const LeafNode<K, V>) == Some(parent) &&
+                leaf.parent_idx.assume_init() == parent_idx
+        }
+        None => leaf.parent.is_none()
+    };
+    assert!(
+        len <= BTREE_CAPACITY && parent_matches,
+        "the layout of std::collections::BTreeMap has changed"
+    );
+
+    nodes.push((node, height));
+    for i in 0..len + 1 {
+        if height > 0 {
+            let internal = &*(node as *const InternalNode<K, V>);
+            let child = internal.edges[i].assume_init().as_ptr();
+            walk_btree(child, height - 1, Some((node, i as u16)), nodes, entries);
+        }
+        if i < len {
+            entries.push((leaf.keys[i].as_ptr(), leaf.vals[i].as_ptr()));
+        }
+    }
+}
+
+// The size, alignment and dump function of a node of the given height
+fn btree_node_layout<K: Dump, V: Dump, D: ?Sized + Dumper>(height: usize) -> (usize, usize, DumpFunction<D>) {
+    if height == 0 {
+        (
+            std::mem::size_of::<LeafNode<K, V>>(),
+            std::mem::align_of::<LeafNode<K, V>>(),
+            unsafe { std::mem::transmute::<fn(&LeafNode<K, V>, &mut D), DumpFunction<D>>(dump_btree_leaf) }
+        )
+    } else {
+        (
+            std::mem::size_of::<InternalNode<K, V>>(),
+            std::mem::align_of::<InternalNode<K, V>>(),
+            unsafe { std::mem::transmute::<fn(&InternalNode<K, V>, &mut D), DumpFunction<D>>(dump_btree_internal) }
+        )
+    }
+}
+
+// Adds the initialised fields of 'node' to 'list'
+// (the nodes they point to must have already been referenced, as we don't know their heights here)
+fn add_btree_leaf_fields<K: Dump, V: Dump, D: ?Sized + Dumper>(node: &LeafNode<K, V>, list: &mut DumpList<D>) {
+    match node.parent {
+        Some(ref parent) => {
+            list.add(unsafe { std::mem::transmute::<&std::ptr::NonNull<InternalNode<K, V>>, &*const InternalNode<K, V>>(parent) });
+            list.add(unsafe { &*node.parent_idx.as_ptr() });
+        }
+        None => list.add(unsafe { std::mem::transmute::<&Option<std::ptr::NonNull<InternalNode<K, V>>>, &usize>(&node.parent) })
+    }
+    list.add(&node.len);
+    for i in 0..node.len as usize {
+        list.add(unsafe { &*node.keys[i].as_ptr() });
+        list.add(unsafe { &*node.vals[i].as_ptr() });
+    }
+}
+
+fn dump_btree_leaf<K: Dump, V: Dump, D: ?Sized + Dumper>(node: &LeafNode<K, V>, dumper: &mut D) {
+    dumper.debug_record::<LeafNode<K, V>>("dump");
+    let mut list = DumpList::<D>::new();
+    add_btree_leaf_fields(node, &mut list);
+    list.dump(dumper);
+}
+
+fn dump_btree_internal<K: Dump, V: Dump, D: ?Sized + Dumper>(node: &InternalNode<K, V>, dumper: &mut D) {
+    dumper.debug_record::<InternalNode<K, V>>("dump");
+    let mut list = DumpList::<D>::new();
+    add_btree_leaf_fields(&node.data, &mut list);
+    for i in 0..node.data.len as usize + 1 {
+        list.add(unsafe {
+            std::mem::transmute::<&std::mem::MaybeUninit<std::ptr::NonNull<LeafNode<K, V>>>, &*const LeafNode<K, V>>(&node.edges[i])
+        });
+    }
+    list.dump(dumper);
+}
+
+rodal_named!([K: Named, V: Named] std::collections::BTreeMap<K, V> [type_name!("std::collections::BTreeMap<{}, {}>", K, V)]);
+unsafe impl<K: Dump, V: Dump> Dump for std::collections::BTreeMap<K, V> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        let fake_self: &BTreeMap<K, V> = unsafe { std::mem::transmute(self) };
+        let root = match fake_self.root {
+            Some(ref root) => root,
+            None => {
+                // There are no nodes, so nothing to point to
+                dumper.dump_value(self);
+                return;
+            }
+        };
+
+        // A node dosn't know it's height (and hence wether it's a leaf or an internal node),
+        // so reference all of them now (with the right size), whilst we do
+        for (node, height) in btree_nodes(self) {
+            let (size, alignment, dump) = btree_node_layout::<K, V, D>(height);
+            let node: &LeafNode<K, V> = unsafe { &*node };
+            dumper.reference_object_function_sized_position(node, dump, node, size, alignment);
+        }
+
+        // Dump the root's height and node, and the length, in memory order
+        let mut fields = [Address::new(&root.height), Address::new(&root.node), Address::new(&fake_self.length)];
+        fields.sort();
+        for &field in fields.iter() {
+            if field == Address::new(&root.node) {
+                let (size, alignment, dump) = btree_node_layout::<K, V, D>(root.height);
+                let node: &LeafNode<K, V> = unsafe { root.node.as_ref() };
+                dumper.dump_reference_object_function_sized_position(
+                    node,
+                    dump,
+                    unsafe { std::mem::transmute::<&std::ptr::NonNull<LeafNode<K, V>>, &&LeafNode<K, V>>(&root.node) },
+                    size,
+                    alignment
+                );
+            } else {
+                dumper.dump_value(field.to_ref::<usize>());
+            }
+        }
+    }
+}
+
+// public std::collections::btree_set (src/liballoc/collections/btree/set.rs)
+// A BTreeSet<T> is just a BTreeMap<T, SetValZST> (where SetValZST is a private zero sized type)
+rodal_named!([T: Named] std::collections::BTreeSet<T> [type_name!("std::collections::BTreeSet<{}>", T)]);
+unsafe impl<T: Dump> Dump for std::collections::BTreeSet<T> {
+    fn dump<D: ?Sized + Dumper>(&self, dumper: &mut D) {
+        dumper.debug_record::<Self>("dump");
+        dumper.dump_object(unsafe { std::mem::transmute::<&Self, &std::collections::BTreeMap<T, ()>>(self) });
     }
 }
 
 // Linked list
 // std::collections::linked_list (src/libcollections/linked_list.rs)
 pub struct LinkedList<T> {
-    head: Shared<Node<T>>, //Option
-    tail: Shared<Node<T>>, //Option
+    head: Shared<Node<T>>, // Option (Shared is dumped as a nullable reference)
+    tail: Shared<Node<T>>, // Option
     len: usize,
     marker: std::marker::PhantomData<Box<Node<T>>>
 }
@@ -457,7 +963,8 @@ struct Node<T> {
     prev: Shared<Node<T>>, // Option
     element: T
 }
-rodal_struct!([T: Dump] Node<T>{next, prev, element} [type_name!("collections::linked_list::Node<{}>", T)]);
+// (element may be before or after the pointers, depending on it's alignment)
+rodal_unordered_struct!([T: Dump] Node<T>{next, prev, element} = Node<T> [type_name!("collections::linked_list::Node<{}>", T)]);
 
 //private core::slice (src/libcore/slice/mod.rs)
 #[repr(C)] // Repr<T> has the same layout as &[T]
@@ -493,9 +1000,7 @@ impl<T: Dump> Repr<T> {
 
         dumper.set_position(Address::from_ptr(self.data));
         // Dump the contents of the slice
-        for val in *real_self {
-            dumper.dump_object(val)
-        }
+        dumper.dump_slice(real_self);
     }
 }
 
@@ -511,3 +1016,147 @@ rodal_struct!(*mut str{data, len} = Repr<u8>);
 
 // Just giving things names
 rodal_named!([T: Named] std::thread::JoinHandle<T> [type_name!("std::thread::JoinHandle<{}>", T)]);
+
+// Static checks that our copies of std's types have the same size and alignment as the real ones
+// (their fields are checked by check_std_layouts, since the real fields are private)
+macro_rules! rodal___assert_same_layout {
+    ($($real:ty = $copy:ty);+) => {
+        $(const _: () = assert!(
+            std::mem::size_of::<$real>() == std::mem::size_of::<$copy>() &&
+                std::mem::align_of::<$real>() == std::mem::align_of::<$copy>(),
+            concat!("the layout of ", stringify!($real), " has changed")
+        );)+
+    };
+}
+rodal___assert_same_layout! {
+    [usize; 3] = std::vec::Vec<u64>;
+    [usize; 4] = std::collections::VecDeque<u64>;
+    std::vec::Vec<u64> = std::collections::BinaryHeap<u64>;
+    std::string::String = String;
+    std::sync::Arc<u64> = Arc<u64>;
+    std::sync::Arc<[u64; 3]> = Arc<[u64; 3]>;
+    std::sync::Weak<u64> = *const u8;
+    std::rc::Rc<u64> = Rc<u64>;
+    std::rc::Rc<[u64]> = Repr<u64>;
+    std::rc::Rc<str> = Repr<u8>;
+    std::rc::Weak<u64> = *const u8;
+    std::collections::LinkedList<u64> = LinkedList<u64>;
+    std::cell::OnceCell<u64> = Option<u64>;
+    // (Option relies on these being niche optimised)
+    Option<&'static u64> = &'static u64;
+    Option<std::boxed::Box<u64>> = std::boxed::Box<u64>;
+    Option<std::ptr::NonNull<u64>> = std::ptr::NonNull<u64>;
+    std::collections::BTreeMap<u64, u8> = BTreeMap<u64, u8>;
+    std::collections::BTreeSet<u64> = std::collections::BTreeMap<u64, ()>;
+    std::boxed::Box<u64> = Unique<u64>;
+    &'static [u64] = Repr<u64>;
+    std::boxed::Box<[u64]> = Repr<u64>;
+    &'static str = Repr<u8>
+}
+
+/// Checks that our copies of std's private types match the real ones, by building real values and
+/// reading them through our copies (panicking if they don't match)
+/// The AsmDumper calls this before dumping anything, so a change in std's layouts can't produce a corrupt dump
+pub fn check_std_layouts() {
+    use std::sync::atomic::Ordering;
+
+    // Vec's words should be the pointer, the capacity and the length (in some order)
+    let mut vec = std::vec::Vec::<u64>::with_capacity(5);
+    vec.push(3);
+    let mut words: [usize; 3] = unsafe { std::mem::transmute_copy(&vec) };
+    let mut expected = [vec.as_ptr() as usize, vec.capacity(), vec.len()];
+    words.sort();
+    expected.sort();
+    assert!(words == expected, "the layout of std::vec::Vec has changed");
+
+    // A deque that has wrapped around (vec_deque_buffer checks that exactly one word could be it's buffer)
+    let mut deque = std::collections::VecDeque::<u64>::with_capacity(4);
+    deque.extend(&[1, 2, 3]);
+    deque.pop_front();
+    deque.pop_front();
+    deque.extend(&[4, 5, 6]);
+    vec_deque_buffer(&deque);
+
+    let string = std::string::String::from("rodal");
+    let fake_string: &String = unsafe { std::mem::transmute(&string) };
+    assert!(
+        fake_string.vec.as_ptr() == string.as_ptr() && fake_string.vec.len() == string.len(),
+        "the layout of std::string::String has changed"
+    );
+
+    let arc = std::sync::Arc::new(7u64);
+    let other_arc = arc.clone();
+    let weak = std::sync::Arc::downgrade(&arc);
+    let fake_arc: &Arc<u64> = unsafe { std::mem::transmute(&arc) };
+    let inner: &ArcInner<u64> = unsafe { &*fake_arc.ptr.pointer.0 };
+    assert!(
+        inner.strong.load(Ordering::SeqCst) == 2 && inner.weak.load(Ordering::SeqCst) == 2 &&
+            std::ptr::eq(&inner.data, &*arc),
+        "the layout of std::sync::Arc has changed"
+    );
+    drop(other_arc);
+    drop(weak);
+    let weak: std::sync::Weak<u64> = std::sync::Weak::new();
+    assert!(
+        is_dangling(*unsafe { std::mem::transmute::<&std::sync::Weak<u64>, &&u8>(&weak) }),
+        "the layout of std::sync::Weak has changed"
+    );
+
+    let rc = std::rc::Rc::new(7u64);
+    let weak = std::rc::Rc::downgrade(&rc);
+    let fake_rc: &Rc<u64> = unsafe { std::mem::transmute(&rc) };
+    let rc_box: &RcBox<u64> = unsafe { &*fake_rc.ptr.pointer.0 };
+    assert!(
+        rc_box.strong.get() == 1 && rc_box.weak.get() == 2 && std::ptr::eq(&rc_box.value, &*rc) &&
+            std::ptr::eq(*unsafe { std::mem::transmute::<&std::rc::Weak<u64>, &&RcBox<u64>>(&weak) }, rc_box),
+        "the layout of std::rc::Rc has changed"
+    );
+    let weak: std::rc::Weak<u64> = std::rc::Weak::new();
+    assert!(
+        is_dangling(*unsafe { std::mem::transmute::<&std::rc::Weak<u64>, &&u8>(&weak) }),
+        "the layout of std::rc::Weak has changed"
+    );
+    let rc: std::rc::Rc<[u64]> = std::rc::Rc::from(&[1u64, 2, 3][..]);
+    let fake_rc: &Rc<[u64]> = unsafe { std::mem::transmute(&rc) };
+    let rc_box: &RcBox<[u64]> = unsafe { &*fake_rc.ptr.pointer.0 };
+    let repr: &Repr<u64> = unsafe { std::mem::transmute(&rc) };
+    assert!(
+        rc_box.strong.get() == 1 && rc_box.value.as_ptr() == rc.as_ptr() && repr.len == 3 &&
+            repr.data as *const u8 == rc_box as *const RcBox<[u64]> as *const u8,
+        "the layout of std::rc::Rc<[T]> has changed"
+    );
+
+    let mut list = std::collections::LinkedList::new();
+    list.push_back(1u64);
+    list.push_back(2u64);
+    let fake_list: &LinkedList<u64> = unsafe { std::mem::transmute(&list) };
+    let head: &Node<u64> = unsafe { &*fake_list.head.pointer.0 };
+    let tail: &Node<u64> = unsafe { &*fake_list.tail.pointer.0 };
+    assert!(
+        fake_list.len == 2 && head.element == 1 && tail.element == 2 && head.prev.pointer.0.is_null() &&
+            std::ptr::eq(head.next.pointer.0, tail) && std::ptr::eq(tail.prev.pointer.0, head) &&
+            tail.next.pointer.0.is_null(),
+        "the layout of std::collections::LinkedList has changed"
+    );
+
+    // Enough entries for the tree to have internal nodes (btree_nodes checks the entries it finds)
+    let map: std::collections::BTreeMap<u64, u8> = (0..200).map(|key| (key, key as u8)).collect();
+    let nodes = btree_nodes(&map);
+    assert!(
+        nodes.iter().any(|&(_, height)| height == 0) && nodes.iter().any(|&(_, height)| height > 0),
+        "the layout of std::collections::BTreeMap has changed"
+    );
+
+    let slice: &[u64] = &[1, 2, 3];
+    let repr: &Repr<u64> = unsafe { std::mem::transmute(&slice) };
+    assert!(
+        repr.data == slice.as_ptr() && repr.len == slice.len(),
+        "the layout of slices has changed"
+    );
+    let boxed: std::boxed::Box<[u64]> = vec![1, 2].into_boxed_slice();
+    let repr: &Repr<u64> = unsafe { std::mem::transmute(&boxed) };
+    assert!(
+        repr.data == boxed.as_ptr() && repr.len == boxed.len(),
+        "the layout of std::boxed::Box<[T]> has changed"
+    );
+}
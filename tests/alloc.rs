@@ -0,0 +1,27 @@
+// Tests for the allocator shim on memory outside of a dump
+extern crate libc;
+extern crate rodal;
+
+#[test]
+fn aligned_realloc() {
+    unsafe {
+        rodal::rodal_init_deallocate();
+        let ptr = libc::malloc(100) as *mut u8;
+        for i in 0..100 {
+            *ptr.add(i) = i as u8;
+        }
+
+        // Invalid alignments fail (without touching ptr)
+        for &alignment in &[0, 3, 48] {
+            assert!(rodal::rodal_aligned_realloc(ptr as *mut libc::c_void, alignment, 200).is_null());
+            assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EINVAL));
+        }
+
+        let moved = rodal::rodal_aligned_realloc(ptr as *mut libc::c_void, 256, 200) as *mut u8;
+        assert!(!moved.is_null() && moved as usize & 255 == 0);
+        for i in 0..100 {
+            assert_eq!(*moved.add(i), i as u8);
+        }
+        rodal::rodal_free(moved as *mut libc::c_void);
+    }
+}
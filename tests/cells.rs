@@ -0,0 +1,65 @@
+// Round trip tests for cells, whose private state is dumped in its initial state around their contents
+extern crate rodal;
+
+mod common;
+
+use std::cell::{Cell, OnceCell, RefCell};
+use std::sync::OnceLock;
+
+#[test]
+fn cells() {
+    let cell = Cell::new(7u64);
+    let ref_cell = RefCell::new(vec![1u32, 2, 3]);
+    let once_cell = OnceCell::new();
+    once_cell.set("set".to_string()).unwrap();
+    let empty_once_cell = OnceCell::<String>::new();
+    let once_lock = OnceLock::new();
+    once_lock.set(vec![4u8, 5]).unwrap();
+    let empty_once_lock = OnceLock::<Vec<u8>>::new();
+
+    // Whilst it's dumped, the RefCell is (immutably) borrowed
+    let _borrow = ref_cell.borrow();
+    let path = common::build_image("cells", |dumper| {
+        dumper
+            .dump("cell", &cell)
+            .dump("ref_cell", &ref_cell)
+            .dump("once_cell", &once_cell)
+            .dump("empty_once_cell", &empty_once_cell)
+            .dump("once_lock", &once_lock)
+            .dump("empty_once_lock", &empty_once_lock);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let cell = image.load_name_mut::<Cell<u64>>("cell").unwrap();
+    assert_eq!(cell.get(), 7);
+    cell.set(8);
+    assert_eq!(cell.get(), 8);
+
+    // The loaded RefCell isn't borrowed
+    let ref_cell = image.load_name_ref::<RefCell<Vec<u32>>>("ref_cell").unwrap();
+    assert_eq!(*ref_cell.try_borrow_mut().unwrap(), vec![1, 2, 3]);
+
+    let once_cell = image.load_name_ref::<OnceCell<String>>("once_cell").unwrap();
+    assert_eq!(once_cell.get().map(|value| value.as_str()), Some("set"));
+    let empty_once_cell = image.load_name_ref::<OnceCell<String>>("empty_once_cell").unwrap();
+    assert_eq!(empty_once_cell.get(), None);
+
+    let once_lock = image.load_name_ref::<OnceLock<Vec<u8>>>("once_lock").unwrap();
+    assert_eq!(once_lock.get(), Some(&vec![4, 5]));
+    let empty_once_lock = image.load_name_ref::<OnceLock<Vec<u8>>>("empty_once_lock").unwrap();
+    assert_eq!(empty_once_lock.get(), None);
+    // Its Once is fresh, so it can still be initialised
+    assert_eq!(empty_once_lock.get_or_init(|| vec![6]), &vec![6]);
+}
+
+#[test]
+fn mutably_borrowed_ref_cell() {
+    let ref_cell = RefCell::new(1u32);
+    let _borrow = ref_cell.borrow_mut();
+
+    let mut dumper = rodal::AsmDumper::new(Vec::new());
+    dumper.dump("ref_cell", &ref_cell).dump("answer", &42u64);
+    assert_eq!(dumper.errors().len(), 1);
+    let err = dumper.try_finish().unwrap_err();
+    assert!(err.to_string().contains("std::cell::RefCell<u32>"), "{}", err);
+}
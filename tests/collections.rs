@@ -0,0 +1,204 @@
+// Round trip tests for collections whose elements are dumped as separate objects
+#[macro_use]
+extern crate rodal;
+
+mod common;
+
+use std::collections::{HashMap, HashSet};
+use rodal::Dumper;
+
+// 9 bytes of fields followed by 7 bytes of trailing padding (which rodal_struct! dosn't dump)
+#[derive(Clone, Debug, PartialEq)]
+#[repr(C)]
+struct Padded {
+    a: u64,
+    b: u8,
+}
+rodal_struct!(Padded { a, b });
+
+#[test]
+fn hash_map_and_set() {
+    let map: HashMap<u32, Vec<u8>> = (0..100).map(|i| (i, vec![i as u8; i as usize % 7])).collect();
+    let set: HashSet<u64> = (0..100).map(|i| i * 3).collect();
+    let path = common::build_image("hash_map_and_set", |dumper| {
+        dumper.dump("map", &map).dump("set", &set);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    image
+        .apply_fixups(rodal::Rebuilders::new().register::<HashMap<u32, Vec<u8>>>().register::<HashSet<u64>>())
+        .unwrap();
+    assert_eq!(image.load_name_ref::<HashMap<u32, Vec<u8>>>("map").unwrap(), &map);
+    assert_eq!(image.load_name_ref::<HashSet<u64>>("set").unwrap(), &set);
+}
+
+#[test]
+fn nested_hash_maps() {
+    // The inner maps are dumped as HashStubs, which are smaller than a HashMap
+    let map: HashMap<u32, HashMap<u32, Vec<u8>>> =
+        (0..50).map(|i| (i, (0..i % 5).map(|j| (j, vec![j as u8; 3])).collect())).collect();
+    let sets: HashMap<u16, HashSet<u16>> = (0..20).map(|i| (i, (0..i).collect())).collect();
+    let path = common::build_image("nested_hash_maps", |dumper| {
+        dumper.dump("map", &map).dump("sets", &sets);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    image
+        .apply_fixups(
+            rodal::Rebuilders::new()
+                .register::<HashMap<u32, HashMap<u32, Vec<u8>>>>()
+                .register::<HashMap<u32, Vec<u8>>>()
+                .register::<HashMap<u16, HashSet<u16>>>()
+                .register::<HashSet<u16>>()
+        )
+        .unwrap();
+    assert_eq!(image.load_name_ref::<HashMap<u32, HashMap<u32, Vec<u8>>>>("map").unwrap(), &map);
+    assert_eq!(image.load_name_ref::<HashMap<u16, HashSet<u16>>>("sets").unwrap(), &sets);
+}
+
+#[test]
+fn padded_hash_map_values() {
+    let map: HashMap<u8, Padded> = (0..100).map(|i| (i, Padded { a: i as u64 * 1000, b: i })).collect();
+    let set: HashSet<(u8, u32)> = (0..100).map(|i| (i, i as u32 * 7)).collect();
+    let path = common::build_image("padded_hash_map_values", |dumper| {
+        dumper.dump("map", &map).dump("set", &set);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    image
+        .apply_fixups(rodal::Rebuilders::new().register::<HashMap<u8, Padded>>().register::<HashSet<(u8, u32)>>())
+        .unwrap();
+    assert_eq!(image.load_name_ref::<HashMap<u8, Padded>>("map").unwrap(), &map);
+    assert_eq!(image.load_name_ref::<HashSet<(u8, u32)>>("set").unwrap(), &set);
+}
+
+#[test]
+fn locked_hash_map() {
+    // The lock's suffix must be dumped after the whole HashMap, not just its HashStub
+    let map: HashMap<u32, u64> = (0..10).map(|i| (i, i as u64 * 11)).collect();
+    let lock = std::sync::Mutex::new(map.clone());
+    let path = common::build_image("locked_hash_map", |dumper| {
+        dumper.dump("lock", &lock);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    image.apply_fixups(rodal::Rebuilders::new().register::<HashMap<u32, u64>>()).unwrap();
+    let lock = image.load_name_ref::<std::sync::Mutex<HashMap<u32, u64>>>("lock").unwrap();
+    assert_eq!(*lock.lock().unwrap(), map);
+}
+
+#[test]
+fn unapplied_fixups() {
+    let map: HashMap<u32, u64> = (0..10).map(|i| (i, i as u64)).collect();
+    let path = common::build_image("unapplied_fixups", |dumper| {
+        dumper.dump("map", &map).dump("answer", &42u64);
+    });
+
+    // Nothing can be loaded until the stubs are rebuilt, even roots that aren't stubs
+    let image = rodal::Image::open(path).unwrap();
+    assert_eq!(image.load_name_ref::<u64>("answer"), Err(rodal::LoadError::FixupsNotApplied));
+    assert_eq!(image.load_tags(), Err(rodal::LoadError::FixupsNotApplied));
+    image.apply_fixups(rodal::Rebuilders::new().register::<HashMap<u32, u64>>()).unwrap();
+    assert_eq!(image.load_name_ref::<u64>("answer").unwrap(), &42);
+    assert_eq!(image.load_name_ref::<HashMap<u32, u64>>("map").unwrap(), &map);
+}
+
+#[test]
+fn tags_are_rebuilt() {
+    let answer = 42u64;
+    let name = "tags_are_rebuilt";
+    {
+        let mut dumper = rodal::AsmDumper::new(std::fs::File::create(common::asm_path(name)).unwrap());
+        dumper.dump("answer", &answer);
+        dumper.tag_reference(&answer, 7);
+        dumper.dump_tags();
+    }
+
+    // The tags are a HashMap, whose type Rebuilders::new already registers
+    let image = rodal::Image::open(common::assemble(name)).unwrap();
+    image.apply_fixups(&rodal::Rebuilders::new()).unwrap();
+    let answer = image.load_name_ref::<u64>("answer").unwrap();
+    let tags = image.load_tags().unwrap();
+    assert_eq!(tags.len(), 1);
+    assert_eq!(tags[&7], vec![answer as *const u64 as *const ()]);
+    // The vectors' buffers are in the dump, and we aren't using the allocator shim
+    std::mem::forget(tags);
+}
+
+#[test]
+#[should_panic(expected = "inside the elements of a std::collections::HashMap")]
+fn reference_into_hash_map() {
+    let map: HashMap<u32, u64> = (0..10).map(|i| (i, i as u64)).collect();
+    let value: &u64 = &map[&3];
+    common::build_image("reference_into_hash_map", |dumper| {
+        dumper.dump("map", &map).dump("value", &value);
+    });
+}
+
+#[test]
+#[should_panic(expected = "inside the elements of a std::collections::HashSet")]
+fn reference_into_hash_set_before_it() {
+    let set: HashSet<u64> = (0..10).collect();
+    let value: &u64 = set.get(&3).unwrap();
+    common::build_image("reference_into_hash_set_before_it", |dumper| {
+        dumper.dump("value", &value).dump("set", &set);
+    });
+}
+
+#[test]
+fn btree_map_and_set() {
+    // More than 11 (a leaf's capacity) entries, so the trees have internal nodes (and the large ones have several levels)
+    let small: std::collections::BTreeMap<u32, u64> = (0..12).map(|i| (i, i as u64 * 3)).collect();
+    let large: std::collections::BTreeMap<u32, String> = (0..1000).map(|i| (i * 7, format!("value {}", i))).collect();
+    let set: std::collections::BTreeSet<u64> = (0..500).map(|i| i * i).collect();
+    let empty = std::collections::BTreeMap::<u32, u64>::new();
+    let path = common::build_image("btree_map_and_set", |dumper| {
+        dumper.dump("small", &small).dump("large", &large).dump("set", &set).dump("empty", &empty);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_small = image.load_name_ref::<std::collections::BTreeMap<u32, u64>>("small").unwrap();
+    let loaded_large = image.load_name_ref::<std::collections::BTreeMap<u32, String>>("large").unwrap();
+    let loaded_set = image.load_name_ref::<std::collections::BTreeSet<u64>>("set").unwrap();
+    assert_eq!(loaded_small, &small);
+    assert_eq!(loaded_large, &large);
+    assert_eq!(loaded_set, &set);
+    assert!(image.load_name_ref::<std::collections::BTreeMap<u32, u64>>("empty").unwrap().is_empty());
+
+    // Searching walks down from the root, instead of just along the leaves
+    assert_eq!(loaded_large.get(&(700 * 7)).map(|value| value.as_str()), Some("value 700"));
+    assert_eq!(loaded_large.get(&1), None);
+    assert_eq!(loaded_large.range(35..70).count(), 5);
+    assert!(loaded_set.contains(&(499 * 499)) && !loaded_set.contains(&2));
+    assert_eq!(loaded_small.iter().next_back(), Some((&11, &33)));
+}
+
+#[test]
+fn vec_deques() {
+    // push_front after push_back wraps the head around to the end of the buffer
+    let mut wrapped = std::collections::VecDeque::with_capacity(8);
+    for i in 0..5u64 {
+        wrapped.push_back(i * 10);
+    }
+    wrapped.push_front(1);
+    wrapped.push_front(2);
+    assert!(!wrapped.as_slices().1.is_empty());
+
+    // Converting a Vec reuses its buffer, with the head at 0
+    let strings: Vec<String> = (0..6).map(|i| format!("string {}", i)).collect();
+    let buffer = strings.as_ptr();
+    let contiguous = std::collections::VecDeque::from(strings);
+    assert!(std::ptr::eq(contiguous.as_slices().0.as_ptr(), buffer));
+    let empty = std::collections::VecDeque::<u64>::new();
+    let path = common::build_image("vec_deques", |dumper| {
+        dumper.dump("wrapped", &wrapped).dump("contiguous", &contiguous).dump("empty", &empty);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_wrapped = image.load_name_ref::<std::collections::VecDeque<u64>>("wrapped").unwrap();
+    assert_eq!(loaded_wrapped, &wrapped);
+    assert_eq!(loaded_wrapped.iter().copied().collect::<Vec<u64>>(), vec![2, 1, 0, 10, 20, 30, 40]);
+    assert_eq!(loaded_wrapped.as_slices().0.len(), wrapped.as_slices().0.len());
+    assert_eq!(image.load_name_ref::<std::collections::VecDeque<String>>("contiguous").unwrap(), &contiguous);
+    assert!(image.load_name_ref::<std::collections::VecDeque<u64>>("empty").unwrap().is_empty());
+}
@@ -0,0 +1,36 @@
+// Helpers for round trip tests: dump some roots, assemble them into a shared library, then load it
+use std::fs::File;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Dumps roots with 'dump', and assembles them into lib<name>.so (in cargo's temporary directory)
+// Each test should use a different name, as dlopen will reuse an already loaded library
+pub fn build_image<F: FnOnce(&mut rodal::AsmDumper<File>)>(name: &str, dump: F) -> PathBuf {
+    {
+        let mut dumper = rodal::AsmDumper::new(File::create(asm_path(name)).unwrap());
+        dump(&mut dumper);
+        dumper.finish();
+    }
+    assemble(name)
+}
+
+// The assembly file that build_image writes the dump for 'name' to
+pub fn asm_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{}.s", name))
+}
+
+// Assembles the (possibly modified) assembly file for 'name' into lib<name>.so
+pub fn assemble(name: &str) -> PathBuf {
+    let asm = asm_path(name);
+    let library = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("lib{}.so", name));
+    let status = Command::new(std::env::var("CC").unwrap_or("cc".to_string()))
+        .arg("-shared")
+        .arg("-Wa,--noexecstack")
+        .arg("-o")
+        .arg(&library)
+        .arg(&asm)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "failed to assemble {}", asm.display());
+    library
+}
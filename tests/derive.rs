@@ -0,0 +1,122 @@
+// Round trip tests for #[derive(Dump, Named)]
+#![cfg(feature = "derive")]
+extern crate rodal;
+
+mod common;
+
+use rodal::{Dump, Dumper, Named};
+
+// Dosn't implement Dump, so it can only be dumped as a value
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Id(u64);
+
+fn dump_doubled<D: ?Sized + Dumper>(count: &u32, dumper: &mut D) {
+    dumper.debug_record::<u32>("dump_doubled");
+    dumper.dump_value_here(&(*count * 2));
+}
+
+#[derive(Dump, Named, Debug, PartialEq)]
+pub struct Config {
+    name: String,
+    #[rodal(skip)]
+    cache: Option<Box<u64>>,
+    #[rodal(value)]
+    id: Id,
+    #[rodal(with = dump_doubled)]
+    count: u32,
+}
+
+#[derive(Dump, Named, Debug, PartialEq)]
+pub struct Pair<T>(T, T);
+
+#[derive(Dump, Named, Debug, PartialEq)]
+pub enum Message {
+    Quit,
+    Move { x: i32, y: i64 },
+    Write(String),
+    Hidden(#[rodal(skip)] u64, u8),
+}
+
+#[derive(Dump, Named, Clone, Copy)]
+#[repr(C)]
+pub union Bits {
+    int: u64,
+    float: f64,
+}
+
+#[derive(Dump, Named)]
+#[repr(C)]
+pub struct Point {
+    x: u32,
+    y: u32,
+}
+
+#[derive(Dump, Named)]
+#[repr(C)]
+pub struct Padded {
+    a: u64,
+    b: u8,
+}
+
+#[derive(Dump, Named)]
+pub struct Pointer {
+    target: Box<u32>,
+}
+
+#[derive(Dump, Named)]
+pub struct Raw {
+    #[rodal(value)]
+    target: Box<u32>,
+}
+
+#[test]
+fn names() {
+    assert_eq!(Config::name(), "Config");
+    assert_eq!(Pair::<u64>::name(), "Pair<u64>");
+    assert_eq!(Message::name(), "Message");
+}
+
+// NO_POINTERS is a constant, so these are checked at compile time
+const _: () = {
+    assert!(<Point as Dump>::NO_POINTERS);
+    assert!(<Pair<u32> as Dump>::NO_POINTERS);
+    assert!(<Bits as Dump>::NO_POINTERS);
+    assert!(<Raw as Dump>::NO_POINTERS);
+    assert!(!<Padded as Dump>::NO_POINTERS); // The padding would be dumped as zeros
+    assert!(!<Pointer as Dump>::NO_POINTERS);
+    assert!(!<Pair<String> as Dump>::NO_POINTERS);
+    assert!(!<Config as Dump>::NO_POINTERS);
+    assert!(!<Message as Dump>::NO_POINTERS);
+};
+
+#[test]
+fn derived_round_trips() {
+    let config = Config { name: "config".to_string(), cache: Some(Box::new(9)), id: Id(77), count: 21 };
+    let pairs = vec![Pair("a".to_string(), "b".to_string()), Pair("c".to_string(), "d".to_string())];
+    let messages = vec![
+        Message::Quit,
+        Message::Move { x: -1, y: 1 << 40 },
+        Message::Write("hello".to_string()),
+        Message::Hidden(5, 6),
+    ];
+    let bits = [Bits { int: 3 }, Bits { float: 1.5 }];
+    let path = common::build_image("derived_round_trips", |dumper| {
+        dumper.dump("config", &config).dump("pairs", &pairs).dump("messages", &messages).dump("bits", &bits);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded = image.load_name_ref::<Config>("config").unwrap();
+    assert_eq!(loaded.name, "config");
+    assert_eq!(loaded.cache, None); // Skipped fields are dumped as zeros
+    assert_eq!(loaded.id, Id(77));
+    assert_eq!(loaded.count, 42);
+
+    assert_eq!(image.load_name_ref::<Vec<Pair<String>>>("pairs").unwrap(), &pairs);
+
+    let loaded = image.load_name_ref::<Vec<Message>>("messages").unwrap();
+    assert_eq!(&loaded[..3], &messages[..3]);
+    assert_eq!(loaded[3], Message::Hidden(0, 6));
+
+    let loaded = image.load_name_ref::<[Bits; 2]>("bits").unwrap();
+    assert_eq!(unsafe { (loaded[0].int, loaded[1].float) }, (3, 1.5));
+}
@@ -0,0 +1,97 @@
+// Round trip tests for rodal_enum!
+#[macro_use]
+extern crate rodal;
+
+mod common;
+
+use rodal::Named;
+
+pub mod shapes {
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum Shape {
+        Circle(u32),
+        Rectangle { width: u16, height: u64 },
+        Empty,
+    }
+}
+rodal_enum!(shapes::Shape { (Circle: radius), { Rectangle: width, height }, Empty });
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token<'a, T> {
+    Word(&'a String),
+    Value(T),
+}
+rodal_enum!(['a, T: rodal::Dump] Token<'a, T> { (Word: word), (Value: value) });
+
+#[test]
+fn enum_names() {
+    assert_eq!(shapes::Shape::name(), "shapes::Shape");
+    assert_eq!(Token::<'static, u64>::name(), "Token<u64>");
+}
+
+#[test]
+fn path_and_lifetime_enums() {
+    let shapes = vec![
+        shapes::Shape::Circle(3),
+        shapes::Shape::Rectangle { width: 4, height: 5 },
+        shapes::Shape::Empty,
+    ];
+    // The word is a root, so the reference to it can be dumped
+    let word = "forty two".to_string();
+    let tokens: Vec<Token<u64>> = vec![Token::Word(&word), Token::Value(42)];
+    let path = common::build_image("path_and_lifetime_enums", |dumper| {
+        dumper.dump("shapes", &shapes).dump("word", &word).dump("tokens", &tokens);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    assert_eq!(image.load_name_ref::<Vec<shapes::Shape>>("shapes").unwrap(), &shapes);
+    assert_eq!(image.load_name_ref::<Vec<Token<'static, u64>>>("tokens").unwrap(), &tokens);
+}
+
+// Enums whose discriminant is stored in a niche of one of their fields
+#[derive(Debug, PartialEq)]
+pub enum Reference {
+    To(&'static u8),
+    Nothing,
+}
+rodal_enum!(Reference { (To: target), Nothing });
+
+#[derive(Debug, PartialEq)]
+pub enum Boxed {
+    Full(Box<u32>),
+    Empty,
+}
+rodal_enum!(Boxed { (Full: value), Empty });
+
+#[derive(Debug, PartialEq)]
+pub enum Flag {
+    Set(bool),
+    Unknown,
+}
+rodal_enum!(Flag { (Set: value), Unknown });
+
+#[test]
+fn niche_enums() {
+    assert_eq!(std::mem::size_of::<Reference>(), std::mem::size_of::<&u8>());
+    assert_eq!(std::mem::size_of::<Boxed>(), std::mem::size_of::<Box<u32>>());
+    assert_eq!(std::mem::size_of::<Flag>(), 1);
+
+    let target: &'static u8 = Box::leak(Box::new(7u8));
+    let references = vec![Reference::To(target), Reference::Nothing];
+    let boxes = vec![Boxed::Empty, Boxed::Full(Box::new(3)), Boxed::Empty];
+    let flags = vec![Flag::Set(true), Flag::Unknown, Flag::Set(false)];
+    let path = common::build_image("niche_enums", |dumper| {
+        dumper.dump("target", target).dump("references", &references).dump("boxes", &boxes).dump("flags", &flags);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_target = image.load_name_ref::<u8>("target").unwrap();
+    let loaded_references = image.load_name_ref::<Vec<Reference>>("references").unwrap();
+    assert_eq!(loaded_references, &references);
+    match loaded_references[0] {
+        Reference::To(loaded) => assert!(std::ptr::eq(loaded, loaded_target)),
+        Reference::Nothing => unreachable!(),
+    }
+    assert_eq!(image.load_name_ref::<Vec<Boxed>>("boxes").unwrap(), &boxes);
+    assert_eq!(image.load_name_ref::<Vec<Flag>>("flags").unwrap(), &flags);
+}
@@ -0,0 +1,59 @@
+// Round trip tests for finding roots in a loaded dump
+extern crate rodal;
+
+mod common;
+
+use rodal::LoadError;
+
+#[test]
+fn roots_are_checked() {
+    let numbers: Vec<u32> = vec![1, 2, 3];
+    let path = common::build_image("roots_are_checked", |dumper| {
+        dumper.dump("numbers", &numbers).dump("answer", &42u64);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    assert_eq!(image.load_name_ref::<Vec<u32>>("numbers").unwrap(), &numbers);
+    assert_eq!(image.load_name_move::<u64>("answer").unwrap(), 42);
+    match image.load_name_ref::<u32>("answer") {
+        Err(LoadError::TypeMismatch { expected_size: 4, found_size: 8, .. }) => {}
+        result => panic!("expected a TypeMismatch, got {:?}", result),
+    }
+
+    // Symbols that aren't objects in the dump are never read
+    for &name in &["RODAL_START", "RODAL_END", "missing"] {
+        assert_eq!(image.find_name::<u64>(name), Err(LoadError::MissingSymbol(name.to_string())));
+    }
+    // Objects that aren't roots are still found through their symbol
+    match image.find_name::<u8>("RODAL_ROOTS") {
+        Err(LoadError::TypeMismatch { expected_size: 1, .. }) => {}
+        result => panic!("expected a TypeMismatch, got {:?}", result),
+    }
+
+    let names: Vec<&str> = image.roots().unwrap().map(|root| root.name).collect();
+    assert_eq!(names, vec!["answer", "numbers"]);
+}
+
+// Dumps a root, and then replaces the format version after RODAL_MAGIC in the assembly with 'patch'
+fn build_patched_image(name: &str, patch: &str) -> std::path::PathBuf {
+    common::build_image(name, |dumper| {
+        dumper.dump("answer", &42u64);
+    });
+    let asm = std::fs::read_to_string(common::asm_path(name)).unwrap();
+    let magic = format!(" {}\n", rodal::RODAL_MAGIC);
+    let version = format!(" {}\n", rodal::RODAL_FORMAT_VERSION);
+    let start = asm.find(&magic).unwrap() + magic.len();
+    let end = start + asm[start..].find('\n').unwrap() + 1;
+    assert!(asm[start..end].ends_with(&version));
+    let patched = format!("{}{}{}", &asm[..start], asm[start..end].replace(&version, patch), &asm[end..]);
+    std::fs::write(common::asm_path(name), patched).unwrap();
+    common::assemble(name)
+}
+
+#[test]
+fn format_is_checked() {
+    match rodal::Image::open(build_patched_image("format_is_checked", " 0\n")) {
+        Err(err) => assert_eq!(err, LoadError::UnsupportedVersion(0)),
+        Ok(_) => panic!("loaded a dump with the wrong format version"),
+    }
+}
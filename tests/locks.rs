@@ -0,0 +1,58 @@
+// Round trip tests for locks, which are dumped in their initial state around their contents
+extern crate rodal;
+
+mod common;
+
+use std::sync::{Mutex, RwLock};
+
+// Runs 'hold', which should panic whilst holding a lock (and so poison it)
+fn poison<F: FnOnce() + std::panic::UnwindSafe>(hold: F) {
+    assert!(std::panic::catch_unwind(hold).is_err());
+}
+
+#[test]
+fn poisoned_locks() {
+    let mutex = Mutex::new(vec![1u32, 2, 3]);
+    let rw_lock = RwLock::new(vec![4u32, 5]);
+    poison(|| {
+        let _guard = mutex.lock().unwrap();
+        panic!("poisoning the mutex");
+    });
+    poison(|| {
+        let _guard = rw_lock.write().unwrap();
+        panic!("poisoning the rwlock");
+    });
+    assert!(mutex.is_poisoned() && rw_lock.is_poisoned());
+
+    let path = common::build_image("poisoned_locks", |dumper| {
+        dumper.dump("mutex", &mutex).dump("rw_lock", &rw_lock);
+    });
+
+    // The contents are kept, but the loaded locks are fresh
+    let image = rodal::Image::open(path).unwrap();
+    let mutex = image.load_name_ref::<Mutex<Vec<u32>>>("mutex").unwrap();
+    let rw_lock = image.load_name_ref::<RwLock<Vec<u32>>>("rw_lock").unwrap();
+    assert!(!mutex.is_poisoned() && !rw_lock.is_poisoned());
+    assert_eq!(*mutex.try_lock().unwrap(), vec![1, 2, 3]);
+    assert_eq!(*rw_lock.try_read().unwrap(), vec![4, 5]);
+}
+
+#[test]
+#[should_panic(expected = "can't dump a std::sync::Mutex<u32> whilst it is locked")]
+fn locked_mutex() {
+    let mutex = Mutex::new(1u32);
+    let _guard = mutex.lock().unwrap();
+    common::build_image("locked_mutex", |dumper| {
+        dumper.dump("mutex", &mutex);
+    });
+}
+
+#[test]
+#[should_panic(expected = "can't dump a std::sync::RwLock<u32> whilst it is write locked")]
+fn write_locked_rw_lock() {
+    let rw_lock = RwLock::new(1u32);
+    let _guard = rw_lock.write().unwrap();
+    common::build_image("write_locked_rw_lock", |dumper| {
+        dumper.dump("rw_lock", &rw_lock);
+    });
+}
@@ -0,0 +1,74 @@
+// Round trip tests for loading write protected dumps
+extern crate libc;
+extern crate rodal;
+
+mod common;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static FAULTS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn count_fault(_: libc::c_int, _: *mut libc::siginfo_t, _: *mut libc::c_void) {
+    FAULTS.fetch_add(1, Ordering::SeqCst);
+}
+
+fn action_address() -> usize {
+    count_fault as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void) as usize
+}
+
+// Everything is in one test, as the signal handlers are global
+#[test]
+fn write_protect_with_fixups() {
+    // Installed before rodal's, which should pass on any faults outside of a dump to it
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = action_address();
+        action.sa_flags = libc::SA_SIGINFO;
+        libc::sigemptyset(&mut action.sa_mask);
+        assert!(libc::sigaction(libc::SIGSEGV, &action, std::ptr::null_mut()) == 0);
+    }
+
+    let map: HashMap<u32, Vec<u64>> = (0..100).map(|i| (i, vec![i as u64; i as usize % 4])).collect();
+    let path = common::build_image("write_protect_with_fixups", |dumper| {
+        dumper.set_object_table(true);
+        dumper.dump("map", &map);
+    });
+
+    let mut rebuilders = rodal::Rebuilders::new();
+    rebuilders.register::<HashMap<u32, Vec<u64>>>();
+    let options = rodal::LoadOptions {
+        write_protect: true,
+        rebuilders: Some(&rebuilders),
+    };
+    let image = rodal::Image::open_with_options(path, options).unwrap();
+    assert_eq!(image.load_name_ref::<HashMap<u32, Vec<u64>>>("map").unwrap(), &map);
+
+    // Both faults should be passed on to our handler, without uninstalling rodal's
+    unsafe {
+        libc::raise(libc::SIGSEGV);
+        libc::raise(libc::SIGSEGV);
+        let mut current: libc::sigaction = std::mem::zeroed();
+        assert!(libc::sigaction(libc::SIGSEGV, std::ptr::null(), &mut current) == 0);
+        assert!(current.sa_sigaction != action_address());
+    }
+    assert_eq!(FAULTS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn write_protect_without_rebuilders() {
+    // The map's stub can't be rebuilt once the dump is protected, so this has to fail
+    let map: HashMap<u8, u8> = (0..10).map(|i| (i, i)).collect();
+    let path = common::build_image("write_protect_without_rebuilders", |dumper| {
+        dumper.dump("map", &map);
+    });
+
+    let options = rodal::LoadOptions {
+        write_protect: true,
+        rebuilders: None,
+    };
+    match rodal::Image::open_with_options(path, options) {
+        Err(rodal::LoadError::MissingRebuild(_)) => {}
+        result => panic!("expected MissingRebuild, got {:?}", result.map(|_| ())),
+    }
+}
@@ -0,0 +1,91 @@
+// Round trip tests for reference counted pointers, and their dumped counts
+extern crate rodal;
+
+mod common;
+
+use std::rc::{Rc, Weak};
+
+#[test]
+fn rc_slices_and_weaks() {
+    let slice: Rc<[u32]> = Rc::from(vec![1, 2, 3]);
+    let slice_clone = slice.clone();
+    let string: Rc<str> = Rc::from("shared");
+    let value = Rc::new(5u64);
+    let weak = Rc::downgrade(&value);
+    let dangling = Weak::<u64>::new();
+    let path = common::build_image("rc_slices_and_weaks", |dumper| {
+        dumper
+            .dump("slice", &slice)
+            .dump("slice_clone", &slice_clone)
+            .dump("string", &string)
+            .dump("value", &value)
+            .dump("weak", &weak)
+            .dump("dangling", &dangling);
+    });
+
+    // Without recounting, the counts are those when the dump was made
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_slice = image.load_name_ref::<Rc<[u32]>>("slice").unwrap();
+    let loaded_clone = image.load_name_ref::<Rc<[u32]>>("slice_clone").unwrap();
+    assert_eq!(&**loaded_slice, &[1, 2, 3]);
+    assert!(Rc::ptr_eq(loaded_slice, loaded_clone));
+    assert_eq!((Rc::strong_count(loaded_slice), Rc::weak_count(loaded_slice)), (2, 0));
+
+    let loaded_string = image.load_name_ref::<Rc<str>>("string").unwrap();
+    assert_eq!(&**loaded_string, "shared");
+    assert_eq!(Rc::strong_count(loaded_string), 1);
+
+    let loaded_value = image.load_name_ref::<Rc<u64>>("value").unwrap();
+    let loaded_weak = image.load_name_ref::<Weak<u64>>("weak").unwrap();
+    assert_eq!((Rc::strong_count(loaded_value), Rc::weak_count(loaded_value)), (1, 1));
+    assert!(Rc::ptr_eq(&loaded_weak.upgrade().unwrap(), loaded_value));
+
+    let loaded_dangling = image.load_name_ref::<Weak<u64>>("dangling").unwrap();
+    assert!(loaded_dangling.upgrade().is_none());
+    assert_eq!(loaded_dangling.as_ptr() as usize, dangling.as_ptr() as usize);
+}
+
+#[test]
+fn recounted_rc_slices() {
+    // The handles that aren't dumped aren't counted
+    let slice: Rc<[u64]> = Rc::from(vec![7, 8]);
+    let extra = vec![slice.clone(), slice.clone()];
+    let extra_weak = Rc::downgrade(&slice);
+    let path = common::build_image("recounted_rc_slices", |dumper| {
+        dumper.set_recount_references(true);
+        dumper.dump("slice", &slice);
+    });
+    assert_eq!((Rc::strong_count(&slice), Rc::weak_count(&slice)), (3, 1));
+    drop((extra, extra_weak));
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_slice = image.load_name_ref::<Rc<[u64]>>("slice").unwrap();
+    assert_eq!(&**loaded_slice, &[7, 8]);
+    assert_eq!((Rc::strong_count(loaded_slice), Rc::weak_count(loaded_slice)), (1, 0));
+}
+
+#[test]
+fn recounted_weaks() {
+    let shared = Rc::new(3u64);
+    let extra = shared.clone();
+    let weak = Rc::downgrade(&shared);
+    let extra_weak = Rc::downgrade(&shared);
+    // Only reached through a Weak, so it's dumped with a strong count of 0
+    let orphan = Rc::new(4u64);
+    let orphan_weak = Rc::downgrade(&orphan);
+    let path = common::build_image("recounted_weaks", |dumper| {
+        dumper.set_recount_references(true);
+        dumper.dump("shared", &shared).dump("weak", &weak).dump("orphan_weak", &orphan_weak);
+    });
+    drop((extra, extra_weak));
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_shared = image.load_name_ref::<Rc<u64>>("shared").unwrap();
+    assert_eq!((Rc::strong_count(loaded_shared), Rc::weak_count(loaded_shared)), (1, 1));
+    assert!(Rc::ptr_eq(&image.load_name_ref::<Weak<u64>>("weak").unwrap().upgrade().unwrap(), loaded_shared));
+
+    let loaded_orphan = image.load_name_ref::<Weak<u64>>("orphan_weak").unwrap();
+    assert_eq!(loaded_orphan.strong_count(), 0);
+    assert!(loaded_orphan.upgrade().is_none());
+    assert_eq!(unsafe { *loaded_orphan.as_ptr() }, 4);
+}
@@ -0,0 +1,44 @@
+// Round trip tests for rodal_replace!
+#[macro_use]
+extern crate rodal;
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub struct Counter {
+    hits: AtomicUsize,
+    limit: usize,
+}
+rodal_replace!(Counter = |counter| { Counter { hits: AtomicUsize::new(0), limit: counter.limit } });
+
+// Dumped as a sorted copy of it's words (which owns memory that's dumped after the replacement is)
+pub struct Words(Vec<String>);
+rodal_replace!(Words = |words| -> Vec<String> {
+    let mut sorted = words.0.clone();
+    sorted.sort();
+    sorted
+});
+
+pub struct Holder {
+    counter: Counter,
+    words: Words,
+}
+rodal_unordered_struct!(Holder { counter, words });
+
+#[test]
+fn replaced_values() {
+    let holder = Holder {
+        counter: Counter { hits: AtomicUsize::new(42), limit: 7 },
+        words: Words(vec!["pear".to_string(), "apple".to_string(), "fig".to_string()]),
+    };
+    let path = common::build_image("replaced_values", |dumper| {
+        dumper.dump("holder", &holder);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let holder = image.load_name_ref::<Holder>("holder").unwrap();
+    assert_eq!(holder.counter.hits.load(Ordering::SeqCst), 0);
+    assert_eq!(holder.counter.limit, 7);
+    assert_eq!(holder.words.0, vec!["apple", "fig", "pear"]);
+}
@@ -0,0 +1,57 @@
+// Round trip tests for Dumper::dump_slice, which dumps slices of NO_POINTERS types as a single value
+#[macro_use]
+extern crate rodal;
+
+mod common;
+
+use rodal::Dump;
+
+// Dumped as raw bytes, so its padding is dumped as is
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C)]
+pub struct Sample {
+    value: u64,
+    flag: u8,
+}
+rodal_value!(Sample);
+
+// Every element type below is dumped with a single value
+const _: () = assert!(
+    <u64 as Dump>::NO_POINTERS && <[u32; 3] as Dump>::NO_POINTERS &&
+        <(u32, u32) as Dump>::NO_POINTERS && <Sample as Dump>::NO_POINTERS
+);
+
+fn bytes<T>(slice: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(slice.as_ptr() as *const u8, std::mem::size_of_val(slice)) }
+}
+
+#[test]
+fn no_pointers_slices() {
+    let words: Vec<u64> = (0..1000).map(|i| i * 0x0101_0101).collect();
+    let triples: Vec<[u32; 3]> = (0..50).map(|i| [i, i + 1, i + 2]).collect();
+    let pairs: Box<[(u32, u32)]> = (0..20).map(|i| (i, !i)).collect();
+
+    // Fill the padding with a pattern, so we can tell that it's dumped
+    let mut samples: Vec<Sample> = Vec::with_capacity(10);
+    unsafe {
+        std::ptr::write_bytes(samples.as_mut_ptr(), 0xab, 10);
+        for i in 0..10 {
+            let sample = samples.as_mut_ptr().add(i);
+            std::ptr::addr_of_mut!((*sample).value).write(i as u64 * 1000);
+            std::ptr::addr_of_mut!((*sample).flag).write(i as u8);
+        }
+        samples.set_len(10);
+    }
+    let path = common::build_image("no_pointers_slices", |dumper| {
+        dumper.dump("words", &words).dump("triples", &triples).dump("pairs", &pairs).dump("samples", &samples);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    assert_eq!(image.load_name_ref::<Vec<u64>>("words").unwrap(), &words);
+    assert_eq!(image.load_name_ref::<Vec<[u32; 3]>>("triples").unwrap(), &triples);
+    assert_eq!(image.load_name_ref::<Box<[(u32, u32)]>>("pairs").unwrap(), &pairs);
+    let loaded = image.load_name_ref::<Vec<Sample>>("samples").unwrap();
+    assert_eq!(loaded, &samples);
+    assert_eq!(bytes(loaded), bytes(&samples));
+    assert_eq!(bytes(loaded)[std::mem::size_of::<Sample>() - 1], 0xab);
+}
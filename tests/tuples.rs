@@ -0,0 +1,70 @@
+// Round trip tests for tuples and arrays, whose elements are dumped in memory order
+extern crate rodal;
+
+mod common;
+
+use rodal::{Dump, Named};
+
+type Padded = (u8, u64);
+// The padding means it can't be dumped as a single value
+const _: () = assert!(!<Padded as Dump>::NO_POINTERS);
+
+fn bytes<T>(value: &T) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(value as *const T as *const u8, std::mem::size_of::<T>()) }
+}
+
+// The offset of the u8 in a Padded (the other 7 bytes of its word are padding)
+fn byte_offset() -> usize {
+    let tuple: Padded = (0, 0);
+    &tuple.0 as *const u8 as usize - &tuple as *const Padded as usize
+}
+
+#[test]
+fn padded_tuples_and_arrays() {
+    assert_eq!(std::mem::size_of::<Padded>(), 16);
+    let tuple: Padded = (1, 2);
+    let array: [Padded; 3] = [(3, 4), (5, 6), (7, 8)];
+    let path = common::build_image("padded_tuples_and_arrays", |dumper| {
+        dumper.dump("tuple", &tuple).dump("array", &array);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded_tuple = image.load_name_ref::<Padded>("tuple").unwrap();
+    let loaded_array = image.load_name_ref::<[Padded; 3]>("array").unwrap();
+    assert_eq!(loaded_tuple, &tuple);
+    assert_eq!(loaded_array, &array);
+
+    // The padding is dumped as zeros
+    let byte = byte_offset();
+    for loaded in std::iter::once(loaded_tuple).chain(loaded_array.iter()) {
+        let padding: Vec<u8> = ((byte & !7)..(byte & !7) + 8).filter(|&i| i != byte).map(|i| bytes(loaded)[i]).collect();
+        assert_eq!(padding, vec![0; 7]);
+    }
+}
+
+type Big = (
+    u8, u16, u32, u64, u8, u16, u32, u64,
+    u8, u16, u32, u64, u8, u16, u32, u64,
+    u8, u16, u32, u64, u8, u16, u32, u64,
+    u8, u16, u32, u64, u8, u16, u32, String,
+);
+
+#[test]
+fn tuple_of_32() {
+    let big: Big = (
+        0, 1, 2, 3, 4, 5, 6, 7,
+        8, 9, 10, 11, 12, 13, 14, 15,
+        16, 17, 18, 19, 20, 21, 22, 23,
+        24, 25, 26, 27, 28, 29, 30, "thirty one".to_string(),
+    );
+    assert!(Big::name().starts_with("(u8, u16, u32, u64, ") && Big::name().ends_with(", u32, std::string::String)"));
+    let path = common::build_image("tuple_of_32", |dumper| {
+        dumper.dump("big", &big);
+    });
+
+    let image = rodal::Image::open(path).unwrap();
+    let loaded = image.load_name_ref::<Big>("big").unwrap();
+    assert_eq!((loaded.0, loaded.1, loaded.2, loaded.3), (0, 1, 2, 3));
+    assert_eq!((loaded.12, loaded.17, loaded.22, loaded.27), (12, 17, 22, 27));
+    assert_eq!((loaded.28, loaded.29, loaded.30, &loaded.31), (28, 29, 30, &big.31));
+}